@@ -0,0 +1,96 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+
+use syn::{
+    Data,
+    DeriveInput,
+    Fields,
+    Ident
+};
+
+// `#[derive(ResourceBinding)]` on a struct of bindings, e.g.
+//
+//     #[derive(ResourceBinding)]
+//     struct GBuffer {
+//         albedo: RenderTargetResource,
+//         normals: RenderTargetResource,
+//         depth: DepthWriteResource
+//     }
+//
+// also declares `GBufferPhysical` with the same fields holding the physical
+// handles, which is what the pass's exec closure receives.
+//
+// proc macros have no `$crate`, so the generated code names everything through
+// absolute paths like `::atsssvt::framegraph::ResourceBinding`. this only
+// resolves in crates with `atsssvt` at their root, inside `atsssvt` itself
+// that's the `extern crate self as atsssvt;` in main.rs, dependents get it by
+// depending on the crate under that name
+#[proc_macro_derive(ResourceBinding)]
+pub fn derive_resource_binding(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = syn::parse(input).unwrap();
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let physical = Ident::new(&format!("{}Physical", name), Span::call_site());
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => panic!("ResourceBinding can only be derived for structs with named fields")
+        },
+        _ => panic!("ResourceBinding can only be derived for structs")
+    };
+
+    let physical_fields = fields.iter().map(|field| {
+        let vis = &field.vis;
+        let ident = &field.ident;
+        let ty = &field.ty;
+
+        quote! { #vis #ident: <#ty as ::atsssvt::framegraph::ResourceBinding>::PhysicalResource }
+    });
+
+    let virtual_resources = fields.iter().map(|field| {
+        let ident = &field.ident;
+
+        quote! { resources.extend(::atsssvt::framegraph::ResourceBinding::get_virtual_resources(&self.#ident)); }
+    });
+
+    // fields are resolved in declaration order, the order they were listed in
+    let resolved = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+
+        quote! { #ident: <#ty as ::atsssvt::framegraph::ResourceBinding>::resolve(backend, resources) }
+    });
+
+    let expanded = quote! {
+        #vis struct #physical {
+            #(#physical_fields),*
+        }
+
+        impl ::atsssvt::framegraph::ResourceBinding for #name {
+            type PhysicalResource = #physical;
+
+            fn get_virtual_resources(&self) -> Vec<::atsssvt::framegraph::FrameGraphResource> {
+                let mut resources = Vec::new();
+                #(#virtual_resources)*
+
+                resources
+            }
+
+            fn resolve<B: ::atsssvt::backend::Backend>(backend: &B, resources: &mut ::std::slice::Iter<::atsssvt::framegraph::FrameGraphResource>) -> #physical {
+                #physical {
+                    #(#resolved),*
+                }
+            }
+        }
+    };
+
+    expanded.into()
+}
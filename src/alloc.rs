@@ -1,18 +1,19 @@
+use backend::{
+    Backend,
+    ClearValue,
+    CpuDescriptorHandle,
+    GpuDescriptorHandle,
+    HeapFlags,
+    ResourceDesc,
+    ResourceStates
+};
+
 use framegraph::{
     ResourceView,
-    ResourceViewDesc,
     TransientResource,
     TransientResourceLifetime
 };
 
-use winapi::um::d3d12::*;
-use winapi::Interface;
-
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
-
-use std::ptr;
-
 #[derive(Debug, Copy, Clone)]
 pub struct MemoryRegion {
     offset: u64,
@@ -31,10 +32,11 @@ impl MemoryRegion {
         }
     }
 
-    // regions intersect fully (memory & timeline)
+    // regions intersect fully (memory & timeline), `end` is the last pass
+    // using the region so it still counts
     pub fn intersects(&self, other: MemoryRegion) -> bool {
-        self.start < other.end &&
-        self.end > other.start &&
+        self.start <= other.end &&
+        self.end >= other.start &&
         self.offset < other.offset + other.size &&
         self.offset + self.size > other.offset
     }
@@ -49,14 +51,16 @@ impl MemoryRegion {
 #[derive(Debug)]
 pub struct HeapBin {
     size: u64,
+    flags: HeapFlags,
     scanlines: Vec<u64>,
     elements: Vec<MemoryRegion>
 }
 
 impl HeapBin {
-    pub fn new(size: u64) -> HeapBin {
+    pub fn new(size: u64, flags: HeapFlags) -> HeapBin {
         HeapBin {
             size,
+            flags,
             scanlines: vec![0u64],
             elements: Vec::new()
         }
@@ -64,6 +68,7 @@ impl HeapBin {
 
     fn clear(&mut self) {
         self.scanlines.clear();
+        self.scanlines.push(0);
         self.elements.clear();
     }
 
@@ -109,41 +114,62 @@ impl HeapBin {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct CachedResource {
+    size: u64,
+    lifetime: TransientResourceLifetime,
+    desc: ResourceDesc,
+    state: ResourceStates,
+    subresources: u32,
+    clear_value: Option<ClearValue>,
+    name: String,
+    pass: Option<String>,
+    // imported and culled resources aren't placed in a heap
+    placed: bool
+}
+
 #[derive(Derivative)]
-#[derivative(Debug)]
-pub struct HeapMemoryCacheEntry {
+#[derivative(Debug(bound=""))]
+pub struct HeapMemoryCacheEntry<B: Backend> {
     hash: u64,
+    // assigned when the layout is first packed and kept while it's cached,
+    // unlike its position in the cache
+    id: u64,
     #[derivative(Debug="ignore")]
-    resources: Vec<(u64, TransientResourceLifetime, D3D12_RESOURCE_DESC, &'static str)>,
+    resources: Vec<CachedResource>,
     #[derivative(Debug="ignore")]
     views: Vec<ResourceView>,
 
-    placed_resources: Vec<*mut ID3D12Resource>,
+    placed_resources: Vec<Option<B::Resource>>,
+    // of every subresource, as the last frame using the layout left them
+    states: Vec<Vec<ResourceStates>>,
     #[derivative(Debug="ignore")]
-    gpu_handles: Vec<D3D12_GPU_DESCRIPTOR_HANDLE>,
+    gpu_handles: Vec<GpuDescriptorHandle>,
     #[derivative(Debug="ignore")]
-    cpu_handles: Vec<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    cpu_handles: Vec<CpuDescriptorHandle>,
     indices: Vec<(usize, u64)>
 }
 
-impl HeapMemoryCacheEntry {
+impl<B: Backend> HeapMemoryCacheEntry<B> {
     pub fn new() -> Self {
         HeapMemoryCacheEntry {
             hash: 0u64,
+            id: 0,
             resources: Vec::new(),
             views: Vec::new(),
             placed_resources: Vec::new(),
+            states: Vec::new(),
             gpu_handles: Vec::new(),
             cpu_handles: Vec::new(),
             indices: Vec::new()
         }
     }
 
-    pub fn get_cpu_handle(&self, id: usize) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+    pub fn get_cpu_handle(&self, id: usize) -> CpuDescriptorHandle {
         self.cpu_handles[id]
     }
 
-    pub fn get_gpu_handle(&self, id: usize) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+    pub fn get_gpu_handle(&self, id: usize) -> GpuDescriptorHandle {
         self.gpu_handles[id]
     }
 /*
@@ -153,16 +179,17 @@ impl HeapMemoryCacheEntry {
 }
 
 #[derive(Debug, Copy, Clone)]
-pub struct Heap {
-    heap: *mut ID3D12Heap,
-    size: u64
+pub struct Heap<H> {
+    heap: H,
+    size: u64,
+    flags: HeapFlags
 }
 
-pub struct HeapLayout {
-    heaps: Vec<Heap>
+pub struct HeapLayout<H> {
+    heaps: Vec<Heap<H>>
 }
 
-impl HeapLayout {
+impl<H> HeapLayout<H> {
     fn new() -> Self {
         HeapLayout {
             heaps: Vec::new()
@@ -172,98 +199,53 @@ impl HeapLayout {
 
 // TODO: linear array of 0..virtual_id for both created & views?
 
-#[derive(Debug)]
-pub struct HeapMemoryAllocator {
-    device: *mut ID3D12Device,
-
-    cbv_srv_uav_heap: *mut ID3D12DescriptorHeap,
-    srv_stride: u32,
-
-    rtv_dsv_heap: *mut ID3D12DescriptorHeap,
-    rtv_stride: u32,
-
-    current_layout: Vec<Heap>,
-    cache: [HeapMemoryCacheEntry; 8],
+#[derive(Derivative)]
+#[derivative(Debug(bound=""))]
+pub struct HeapMemoryAllocator<B: Backend> {
+    current_layout: Vec<Heap<B::Heap>>,
+    cache: [HeapMemoryCacheEntry<B>; 8],
+    next_id: u64,
+    // every layout writes its views to the same descriptor slots, this is
+    // the id of the one they currently point into
+    views_written: Option<u64>
 }
 
-impl HeapMemoryAllocator {
-    pub fn new(device: *mut ID3D12Device) -> Self {
-        let (gpu_heap, gpu_stride, cpu_heap, cpu_stride) = unsafe {
-            let mut gpu_heap: *mut ID3D12DescriptorHeap = ptr::null_mut();
-            let mut cpu_heap: *mut ID3D12DescriptorHeap = ptr::null_mut();
-
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                NumDescriptors: 3000,
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0
-            };
-
-            (*device).CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::uuidof(), &mut gpu_heap as *mut *mut _ as *mut *mut _);
-
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                NumDescriptors: 3000,
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0
-            };
-
-            (*device).CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::uuidof(), &mut cpu_heap as *mut *mut _ as *mut *mut _);
-
-            let gpu_stride = (*device).GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
-            let cpu_stride = (*device).GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_RTV);
-
-            (gpu_heap, gpu_stride, cpu_heap, cpu_stride)
-        };
-
+impl<B: Backend> HeapMemoryAllocator<B> {
+    pub fn new() -> Self {
         HeapMemoryAllocator {
-            device: device,
-
-            cbv_srv_uav_heap: gpu_heap,
-            srv_stride: gpu_stride,
-
-            rtv_dsv_heap: cpu_heap,
-            rtv_stride: cpu_stride,
-
             current_layout: Vec::new(),
-            cache: [HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new()]
+            cache: [HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new(),HeapMemoryCacheEntry::new()],
+            next_id: 0,
+            views_written: None
         }
     }
 
-    pub fn get_cpu_handle(&self, id: usize) -> D3D12_CPU_DESCRIPTOR_HANDLE {
-        let mut handle = unsafe { (*self.rtv_dsv_heap).GetCPUDescriptorHandleForHeapStart() };
-        handle.ptr += id * self.rtv_stride as usize;
-
-        handle
+    pub fn current(&self) -> &HeapMemoryCacheEntry<B> {
+        &self.cache[0]
     }
 
-    pub fn get_gpu_handle(&self, id: u64) -> D3D12_GPU_DESCRIPTOR_HANDLE {
-        let mut handle = unsafe { (*self.cbv_srv_uav_heap).GetGPUDescriptorHandleForHeapStart() };
-        handle.ptr += id * self.srv_stride as u64;
-
-        handle
+    pub fn get_placed_resource_ptr(&self, idx: usize) -> Option<B::Resource> {
+        self.current().placed_resources.get(idx).and_then(|resource| *resource)
     }
 
-    pub fn current(&self) -> &HeapMemoryCacheEntry {
-        &self.cache[0]
+    // empty for resources that weren't placed
+    pub fn get_placed_resource_states(&self, idx: usize) -> &[ResourceStates] {
+        self.current().states.get(idx).map_or(&[], |states| &states[..])
     }
 
-    pub fn get_placed_resource_ptr(&self, idx: usize) -> *mut ID3D12Resource {
-        self.current().placed_resources[idx]
-    }
+    // remembers the states a frame left the placed resources of the current
+    // layout in, the next frame using the layout starts from them
+    pub fn set_placed_resource_states(&mut self, states: &[Vec<ResourceStates>]) {
+        let entry = &mut self.cache[0];
 
-    fn resize(&mut self, resources: &Vec<(u64, TransientResourceLifetime, D3D12_RESOURCE_DESC, &'static str)>) {
-        // TODO: do we even need to sort *all* resources?
-        let mut cached_resources: Vec<(u64, TransientResourceLifetime, D3D12_RESOURCE_DESC, &'static str)> = Vec::new();
-        cached_resources.extend(resources);
-        for entry in self.cache.iter() {
-            if entry.hash != 0 {
-                cached_resources.extend(&entry.resources);
+        for (current, state) in entry.states.iter_mut().zip(states) {
+            if !current.is_empty() && current.len() == state.len() {
+                current.clone_from(state);
             }
         }
+    }
 
-        cached_resources.sort_by(|a, b| (b.0).cmp(&a.0));
-
+    fn resize(&mut self, backend: &mut B) {
         let mut layout: Vec<HeapBin> = Vec::new();
 
         for entry in self.cache.iter_mut() {
@@ -275,17 +257,31 @@ impl HeapMemoryAllocator {
                     bin.clear();
                 }
 
-                'r: for (idx, resource) in entry.resources.iter().enumerate() {
+                // pack the largest resources first, but keep the entry itself
+                // in resource order so it can be indexed by resource id
+                let mut order = (0..entry.resources.len()).collect::<Vec<_>>();
+                order.sort_by(|&a, &b| entry.resources[b].size.cmp(&entry.resources[a].size));
+
+                'r: for idx in order {
+                    let resource = &entry.resources[idx];
+                    if !resource.placed {
+                        continue;
+                    }
+
+                    let flags = resource.desc.heap_flags();
+
                     let mut i = 0;
                     loop {
                         if i >= layout.len() {
                             // TODO: rethink how heap sizes are fetched
-                            layout.push(HeapBin::new(entry.resources[i].0));
+                            layout.push(HeapBin::new(resource.size, flags));
                         }
 
-                        if let Some(offset) = layout[i].insert(resource.1, resource.0) {
-                            entry.indices[idx] = (i, offset);
-                            continue 'r;
+                        if layout[i].flags == flags {
+                            if let Some(offset) = layout[i].insert(resource.lifetime, resource.size) {
+                                entry.indices[idx] = (i, offset);
+                                continue 'r;
+                            }
                         }
 
                         i += 1;
@@ -294,141 +290,170 @@ impl HeapMemoryAllocator {
             }
         }
 
-        layout.sort_by(|a, b| (b.size).cmp(&a.size));
-
-        let mut existing = vec![None; self.current_layout.len()];
-        for (heap_idx, heap) in self.current_layout.iter().enumerate() {
-            for (idx, h) in layout.iter().enumerate() {
-                if h.size == heap.size {
-                    if let Some(i) = existing[heap_idx] {
-                        if idx == i {
-                            continue;
-                        }
-                    } else {
-                        existing[heap_idx] = Some(idx);
-                    }            
-                }
+        // every cached layout is placed anew below, and invalidated or evicted
+        // ones not at all, so no later frame uses the old resources (the
+        // backend keeps them alive for the ones in flight). they go before the
+        // heaps they were placed in
+        for entry in self.cache.iter_mut() {
+            for resource in entry.placed_resources.drain(..).flatten() {
+                backend.release_resource(resource);
             }
         }
 
+        // bins are referenced by index from the entries, so heaps are laid out
+        // in bin order, reusing any old heap that matches a bin
+        let mut old_heaps = self.current_layout.iter().map(|&heap| Some(heap)).collect::<Vec<_>>();
         let mut new_heaps = Vec::with_capacity(layout.len());
-        for (idx, heap) in self.current_layout.iter().enumerate() {
-            if existing[idx].is_some() {
+
+        // TODO: implement resource aliasing, needs to be done after all resources have
+        //       been packed
+        for (idx, bin) in layout.iter().enumerate() {
+            let existing = old_heaps.iter().position(|heap| match *heap {
+                Some(heap) => heap.size == bin.size && heap.flags == bin.flags,
+                None => false
+            });
+
+            if let Some(existing) = existing {
+                let heap = old_heaps[existing].take().unwrap();
+
                 println!("Carrying Over Heap #{}: {} B", idx, heap.size);
-                new_heaps.push(*heap)
+                new_heaps.push(heap);
             } else {
-                println!("Deleting Heap #{}: {} B", idx, heap.size);
-                unsafe { (*heap.heap).Release(); }
+                println!("Creating Heap #{}: {} B", idx, bin.size);
+                let h = backend.create_heap(bin.size, bin.flags);
+
+                new_heaps.push(Heap {
+                    heap: h,
+                    size: bin.size,
+                    flags: bin.flags
+                })
             }
         }
 
-        // TODO: implement resource aliasing, needs to be done after all resources have
-        //       been packed
-        for (idx, heap) in layout.iter().enumerate() {
-            if existing.iter().find(|&a| if let &Some(i) = a { i == idx } else { false }).is_some() {
-                continue;
+        for (idx, heap) in old_heaps.into_iter().enumerate() {
+            if let Some(heap) = heap {
+                println!("Deleting Heap #{}: {} B", idx, heap.size);
+                backend.release_heap(heap.heap);
             }
-
-            let h = unsafe {
-                let mut heap_ptr: *mut ID3D12Heap = ::std::mem::zeroed();
-
-                let desc = D3D12_HEAP_DESC {
-                    SizeInBytes: heap.size,
-                    Properties: D3D12_HEAP_PROPERTIES {
-                        Type: D3D12_HEAP_TYPE_DEFAULT,
-                        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
-                        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
-                        CreationNodeMask: 0,
-                        VisibleNodeMask: 0,
-                    },
-                    Alignment: 0,
-                    Flags: D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES
-                };
-
-                println!("Creating Heap #{}: {} B", idx, heap.size);
-                (*self.device).CreateHeap(&desc, &ID3D12Heap::uuidof(), &mut heap_ptr as *mut *mut _ as *mut *mut _);
-
-                heap_ptr
-            };
-
-            new_heaps.push(Heap {
-                heap: h,
-                size: heap.size
-            })
         }
 
-        // TODO: clear previous placed resources
-
         for entry in self.cache.iter_mut() {
             if entry.hash != 0 {
+                entry.placed_resources.clear();
+                entry.states.clear();
+
                 for (idx, &(heap, offset)) in entry.indices.iter().enumerate() {
-                    let mut resource: *mut ID3D12Resource = ptr::null_mut();
-                    unsafe {
-                        (*self.device).CreatePlacedResource(new_heaps[heap].heap, offset, &entry.resources[idx].2, D3D12_RESOURCE_STATE_RENDER_TARGET, ptr::null_mut(), &ID3D12Resource::uuidof(), &mut resource as *mut *mut _ as *mut *mut _);
-                        let name: Vec<u16> = OsStr::new(entry.resources[idx].3).encode_wide().chain(Some(0).into_iter()).collect::<Vec<u16>>();
-                        (*resource).SetName(name.as_ptr());
+                    let resource = &entry.resources[idx];
+                    if !resource.placed {
+                        entry.placed_resources.push(None);
+                        entry.states.push(Vec::new());
+                        continue;
                     }
+
+                    // resources of different cache entries alias the same
+                    // memory, the layout tells them apart in captures
+                    let name = format!("{} ({}, layout {})", resource.name, resource.pass.as_ref().unwrap(), entry.id);
+
+                    let placed = backend.create_placed_resource(new_heaps[heap].heap, offset, &resource.desc, resource.state, resource.clear_value, &name);
                     // TODO: probably should be in some order?
-                    entry.placed_resources.push(resource);
+                    entry.placed_resources.push(Some(placed));
+                    entry.states.push(vec![resource.state; resource.subresources as usize]);
                 }
             }
         }
 
         self.current_layout = new_heaps;
+        // the resources any written views pointed to are gone
+        self.views_written = None;
+    }
+
+    // forgets every cached layout, the next `pack_heap` repacks from scratch
+    // and releases their resources along with the heaps nothing fits in
+    // anymore
+    pub fn invalidate(&mut self) {
+        for entry in self.cache.iter_mut() {
+            entry.hash = 0;
+        }
     }
 
     fn find_entry(&self, hash: u64) -> Option<usize> {
         self.cache.iter().position(|entry| entry.hash == hash)
     }
 
-    fn push_entry(&mut self, hash: u64, resources: Vec<(u64, TransientResourceLifetime, D3D12_RESOURCE_DESC, &'static str)>, views: &Vec<ResourceView>) -> &HeapMemoryCacheEntry {
+    fn push_entry(&mut self, backend: &mut B, hash: u64, resources: Vec<CachedResource>, views: &Vec<ResourceView>) -> &HeapMemoryCacheEntry<B> {
         for i in 0..7 {
             self.cache.swap(7 - i, 7 - i - 1);
         }
 
         {
             self.cache[0].hash = hash;
+            self.cache[0].id = self.next_id;
+            self.next_id += 1;
             self.cache[0].resources.clear();
-            self.cache[0].resources.extend(&resources);
+            self.cache[0].resources.extend(resources.iter().cloned());
 
-            self.resize(&resources);
+            self.cache[0].views.clone_from(views);
 
-            for view in views {
-                let resource = self.cache[0].placed_resources[view.resource_id as usize];
+            self.resize(backend);
+            self.write_views(backend);
+        }
 
-                match view.desc {
-                    ResourceViewDesc::RenderTarget(desc) => {
-                        let handle = self.get_cpu_handle(view.view_id as usize);
+        &self.cache[0]
+    }
 
-                        unsafe {
-                            (*self.device).CreateRenderTargetView(resource, &desc, handle);
-                        }
-                    },
-                    ResourceViewDesc::ShaderResource(desc) => {
+    // points the descriptors at the resources of the current layout, unless
+    // they already do
+    fn write_views(&mut self, backend: &mut B) {
+        let entry = &self.cache[0];
+        if self.views_written == Some(entry.id) {
+            return;
+        }
 
-                    }
-                }
+        for view in &entry.views {
+            // views of imported resources are created by the graph itself,
+            // culled ones have nothing to view
+            if let Some(&Some(resource)) = entry.placed_resources.get(view.resource_id as usize) {
+                backend.create_view(resource, view.view_id, &view.desc);
             }
         }
 
-        &self.cache[0]
+        self.views_written = Some(entry.id);
     }
 
-    pub fn pack_heap(&mut self, resources: &Vec<TransientResource>, views: &Vec<ResourceView>) -> &HeapMemoryCacheEntry {
+    pub fn pack_heap(&mut self, backend: &mut B, resources: &Vec<TransientResource>, views: &Vec<ResourceView>) -> &HeapMemoryCacheEntry<B> {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
 
+        // the views are part of the layout, they're only written when it
+        // becomes current
         let mut hasher = DefaultHasher::new();
         resources.hash(&mut hasher);
+        views.hash(&mut hasher);
         let hash = hasher.finish();
 
         if let Some(entry) = self.find_entry(hash) {
-            &self.cache[entry]
+            // `current` is whatever was packed last
+            self.cache[..entry + 1].rotate_right(1);
+            self.write_views(backend);
+            &self.cache[0]
         } else {
-            let mut resources = resources.iter().map(|r| (r.size, r.lifetime, r.desc, r.name)).collect::<Vec<_>>();
-            resources.sort_by(|a, b| (b.0).cmp(&a.0));
+            let resources = resources.iter().map(|r| {
+                let (mips, slices) = r.subresources();
+
+                CachedResource {
+                    size: r.size,
+                    lifetime: r.lifetime,
+                    desc: r.desc,
+                    state: r.initial_state(),
+                    subresources: mips * slices,
+                    clear_value: r.clear_value(),
+                    name: r.name.clone(),
+                    pass: r.pass.clone(),
+                    placed: !r.imported && !r.culled
+                }
+            }).collect::<Vec<_>>();
 
-            self.push_entry(hash, resources, views)
+            self.push_entry(backend, hash, resources, views)
         }
     }
 
@@ -453,7 +478,7 @@ impl HeapMemoryAllocator {
 
         let mut y = padding;
         for bin in bins {
-            let height = bin.size as f64 / max_height as f64 * heap_height;
+            let height = bin.size as f64 / max_height * heap_height;
 
             let node = svg::node::element::Rectangle::new()
                     .set("x", 0)
@@ -468,7 +493,7 @@ impl HeapMemoryAllocator {
             for region in &bin.elements {
                 let size = region.size;
 
-                let xoff = region.start as f64 / max_width as f64 * heap_width;
+                let xoff = region.start as f64 / max_width * heap_width;
                 let yoff = region.offset as f64 / bin.size as f64 * height;
                 let w = (region.end - region.start) as f64 / max_width * heap_width;
                 let h = region.size as f64 / bin.size as f64 * height;
@@ -503,3 +528,35 @@ impl HeapMemoryAllocator {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lifetime(start: u32, end: u32) -> TransientResourceLifetime {
+        TransientResourceLifetime {
+            start,
+            end
+        }
+    }
+
+    #[test]
+    fn regions_sharing_a_pass_intersect() {
+        let first = MemoryRegion::new(0, 64, 0, 1);
+
+        assert!(first.intersects(MemoryRegion::new(32, 64, 1, 2)));
+        assert!(!first.intersects(MemoryRegion::new(0, 64, 2, 3)));
+        assert!(!first.intersects(MemoryRegion::new(64, 64, 0, 1)));
+    }
+
+    #[test]
+    fn bin_reuses_memory_of_dead_resources() {
+        let mut bin = HeapBin::new(128, HeapFlags::ALLOW_ONLY_RT_DS_TEXTURES);
+
+        assert_eq!(bin.insert(lifetime(0, 1), 64), Some(0));
+        assert_eq!(bin.insert(lifetime(2, 3), 64), Some(0));
+        // alive alongside both
+        assert_eq!(bin.insert(lifetime(1, 2), 64), Some(64));
+        assert_eq!(bin.insert(lifetime(1, 1), 64), None);
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::Debug;
+
+// values mirror their D3D12 counterparts so backends can pass them straight through
+bitflags! {
+    pub struct ResourceStates: u32 {
+        const RENDER_TARGET = 0x4;
+        const UNORDERED_ACCESS = 0x8;
+        const DEPTH_WRITE = 0x10;
+        const DEPTH_READ = 0x20;
+        const NON_PIXEL_SHADER_RESOURCE = 0x40;
+        const PIXEL_SHADER_RESOURCE = 0x80;
+    }
+}
+
+// zero in D3D12, kept out of the bitflags so they don't show up in every
+// `Debug` print
+impl ResourceStates {
+    pub const COMMON: ResourceStates = ResourceStates { bits: 0 };
+    pub const PRESENT: ResourceStates = ResourceStates { bits: 0 };
+}
+
+bitflags! {
+    pub struct ResourceFlags: u32 {
+        const ALLOW_RENDER_TARGET = 0x1;
+        const ALLOW_DEPTH_STENCIL = 0x2;
+        const ALLOW_UNORDERED_ACCESS = 0x4;
+    }
+}
+
+bitflags! {
+    // split barriers, the transition starts at `BEGIN_ONLY` and the resource
+    // can't be used until the matching `END_ONLY`
+    pub struct BarrierFlags: u32 {
+        const BEGIN_ONLY = 0x1;
+        const END_ONLY = 0x2;
+    }
+}
+
+bitflags! {
+    pub struct HeapFlags: u32 {
+        const DENY_BUFFERS = 0x4;
+        const DENY_RT_DS_TEXTURES = 0x40;
+        const DENY_NON_RT_DS_TEXTURES = 0x80;
+
+        const ALLOW_ONLY_BUFFERS = 0xc0;
+        const ALLOW_ONLY_NON_RT_DS_TEXTURES = 0x44;
+        const ALLOW_ONLY_RT_DS_TEXTURES = 0x84;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum QueueType {
+    Graphics,
+    Compute,
+    Copy,
+}
+
+impl QueueType {
+    pub fn index(self) -> usize {
+        match self {
+            QueueType::Graphics => 0,
+            QueueType::Compute => 1,
+            QueueType::Copy => 2,
+        }
+    }
+
+    // whether a command list of this queue can transition into or out of `states`
+    pub fn supports(self, states: ResourceStates) -> bool {
+        match self {
+            QueueType::Graphics => true,
+            QueueType::Compute => !states.intersects(
+                ResourceStates::RENDER_TARGET |
+                ResourceStates::DEPTH_WRITE |
+                ResourceStates::DEPTH_READ |
+                ResourceStates::PIXEL_SHADER_RESOURCE
+            ),
+            QueueType::Copy => states.is_empty()
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Unknown,
+    R8G8B8A8Unorm,
+    R8Unorm,
+    R32Typeless,
+    R32Float,
+    D32Float,
+}
+
+impl Format {
+    // bytes per pixel/element
+    pub fn size(self) -> u64 {
+        match self {
+            Format::Unknown => 0,
+            Format::R8G8B8A8Unorm => 4,
+            Format::R8Unorm => 1,
+            Format::R32Typeless => 4,
+            Format::R32Float => 4,
+            Format::D32Float => 4,
+        }
+    }
+
+    pub fn is_depth(self) -> bool {
+        matches!(self, Format::D32Float)
+    }
+
+    // depth is placed typeless so it can be viewed as both depth and color
+    pub fn typeless(self) -> Format {
+        match self {
+            Format::D32Float => Format::R32Typeless,
+            format => format
+        }
+    }
+
+    // format shaders read the resource through
+    pub fn shader_resource_format(self) -> Format {
+        match self {
+            Format::D32Float => Format::R32Float,
+            format => format
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum ResourceDimension {
+    Buffer,
+    Texture2D,
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct ResourceDesc {
+    pub dimension: ResourceDimension,
+    pub width: u64,
+    pub height: u32,
+    pub depth_or_array_size: u16,
+    pub mip_levels: u16,
+    pub format: Format,
+    pub flags: ResourceFlags,
+}
+
+impl ResourceDesc {
+    // heaps can only hold one category of resource on all hardware, pick the
+    // one this resource belongs to
+    pub fn heap_flags(&self) -> HeapFlags {
+        match self.dimension {
+            ResourceDimension::Buffer => HeapFlags::ALLOW_ONLY_BUFFERS,
+            _ if self.flags.intersects(ResourceFlags::ALLOW_RENDER_TARGET | ResourceFlags::ALLOW_DEPTH_STENCIL) => HeapFlags::ALLOW_ONLY_RT_DS_TEXTURES,
+            _ => HeapFlags::ALLOW_ONLY_NON_RT_DS_TEXTURES
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq)]
+pub enum ResourceViewDesc {
+    // texture views cover `array_size` slices from `first_array_slice` on
+    RenderTarget {
+        format: Format,
+        mip_slice: u32,
+        first_array_slice: u32,
+        array_size: u32
+    },
+    // `ALL_MIPS` views every mip from `most_detailed_mip` on
+    ShaderResource {
+        format: Format,
+        most_detailed_mip: u32,
+        mip_levels: u32,
+        first_array_slice: u32,
+        array_size: u32
+    },
+    UnorderedAccess {
+        format: Format,
+        mip_slice: u32,
+        first_array_slice: u32,
+        array_size: u32
+    },
+    // read-only views can be bound while other passes sample the depth
+    DepthStencil {
+        format: Format,
+        mip_slice: u32,
+        first_array_slice: u32,
+        array_size: u32,
+        read_only: bool
+    },
+    // `stride` is only set for structured buffers, raw buffers use `R32Typeless`
+    BufferShaderResource {
+        format: Format,
+        num_elements: u32,
+        stride: u32,
+        raw: bool
+    },
+    BufferUnorderedAccess {
+        format: Format,
+        num_elements: u32,
+        stride: u32,
+        raw: bool
+    },
+}
+
+// optimized clear value a render target or depth stencil is placed with,
+// clears using the same value are faster on most hardware
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClearValue {
+    Color([f32; 4]),
+    DepthStencil(f32, u8)
+}
+
+// what an attachment holds when a render pass begins
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LoadOp {
+    Clear(ClearValue),
+    Preserve,
+    Discard
+}
+
+// whether an attachment's contents are kept once the render pass ends
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StoreOp {
+    Preserve,
+    Discard
+}
+
+// `view_id` is a render target view, or a depth stencil view for depth
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderPassAttachment {
+    pub view_id: u32,
+    pub format: Format,
+    pub load: LoadOp,
+    pub store: StoreOp
+}
+
+// subresource index of a transition that covers the whole resource
+pub const ALL_SUBRESOURCES: u32 = 0xffffffff;
+
+pub const ALL_MIPS: u32 = 0xffffffff;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ResourceBarrier<R> {
+    // (resource, subresource, before, after, flags), subresources are indexed
+    // by `mip + array_slice * mip_levels`
+    Transition(R, u32, ResourceStates, ResourceStates, BarrierFlags),
+    // `None` aliases from whatever previously occupied the memory
+    Alias(Option<R>, R),
+    // waits for all unordered access writes to the resource to finish
+    Uav(R)
+}
+
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct CpuDescriptorHandle {
+    pub ptr: usize
+}
+
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct GpuDescriptorHandle {
+    pub ptr: u64
+}
+
+pub trait Backend {
+    type Heap: Copy + Debug;
+    type Resource: Copy + Debug;
+    type CommandList: Copy;
+
+    // a command list for `queue` that is ready for recording
+    fn begin_commands(&mut self, queue: QueueType) -> Self::CommandList;
+    // submits `list` once the other queues' fences reached `waits`, and signals
+    // the fence of `queue` with `signal` after it executed
+    fn submit_commands(&mut self, queue: QueueType, list: Self::CommandList, waits: &[(QueueType, u64)], signal: Option<u64>);
+
+    // (size, alignment)
+    fn resource_allocation_info(&self, desc: &ResourceDesc) -> (u64, u64);
+
+    // lists already submitted may still use released heaps and resources,
+    // backends hold on to them until those finished executing
+    fn create_heap(&mut self, size: u64, flags: HeapFlags) -> Self::Heap;
+    fn release_heap(&mut self, heap: Self::Heap);
+
+    fn create_placed_resource(&mut self, heap: Self::Heap, offset: u64, desc: &ResourceDesc, state: ResourceStates, clear_value: Option<ClearValue>, name: &str) -> Self::Resource;
+    fn release_resource(&mut self, resource: Self::Resource);
+    fn create_view(&mut self, resource: Self::Resource, view_id: u32, desc: &ResourceViewDesc);
+
+    fn cpu_handle(&self, view_id: u32) -> CpuDescriptorHandle;
+    fn gpu_handle(&self, view_id: u32) -> GpuDescriptorHandle;
+    // depth stencil views live in a heap of their own
+    fn dsv_handle(&self, view_id: u32) -> CpuDescriptorHandle;
+
+    // recording into a list only needs a shared backend, so `Sync` backends
+    // with `Send` lists and resources can record several lists at once (see
+    // `FrameGraph::exec_parallel`)
+    fn resource_barrier(&self, list: Self::CommandList, barriers: &[ResourceBarrier<Self::Resource>]);
+
+    fn clear_render_target(&self, list: Self::CommandList, view_id: u32, color: [f32; 4]);
+    fn clear_depth_stencil(&self, list: Self::CommandList, view_id: u32, depth: f32, stencil: u8);
+    // contents become undefined, cheaper than a clear when a pass overwrites
+    // everything anyway
+    fn discard_resource(&self, list: Self::CommandList, resource: Self::Resource);
+
+    // binds the attachments and tells the driver what to load and store
+    fn begin_render_pass(&self, list: Self::CommandList, render_targets: &[RenderPassAttachment], depth: Option<RenderPassAttachment>);
+    fn end_render_pass(&self, list: Self::CommandList);
+
+    // `count` timestamp queries along with the readback memory they're
+    // resolved into, recreating them drops any results not read back yet
+    fn create_timestamp_queries(&mut self, count: u32);
+    fn write_timestamp(&self, list: Self::CommandList, index: u32);
+    fn resolve_timestamps(&self, list: Self::CommandList, first: u32, count: u32);
+    // only valid once the lists that resolved them finished executing
+    fn read_timestamps(&self, first: u32, timestamps: &mut [u64]);
+    // ticks per second
+    fn timestamp_frequency(&self) -> u64;
+
+    // debug markers showing up in captures, events nest
+    fn begin_event(&self, list: Self::CommandList, name: &str);
+    fn end_event(&self, list: Self::CommandList);
+
+    // called right before a pass's exec closure runs
+    fn execute_pass(&self, _list: Self::CommandList, _name: &str) {
+    }
+}
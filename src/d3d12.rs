@@ -0,0 +1,971 @@
+use backend::{
+    Backend,
+    ClearValue,
+    CpuDescriptorHandle,
+    Format,
+    GpuDescriptorHandle,
+    HeapFlags,
+    LoadOp,
+    QueueType,
+    RenderPassAttachment,
+    ResourceBarrier,
+    ResourceDesc,
+    ResourceDimension,
+    ResourceFlags,
+    ResourceStates,
+    ResourceViewDesc,
+    StoreOp
+};
+
+use winapi::ctypes::c_void;
+use winapi::um::d3d12::*;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::synchapi::{
+    CreateEventW,
+    WaitForSingleObject
+};
+use winapi::um::winbase::INFINITE;
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::minwindef::FALSE;
+use winapi::Interface;
+
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+
+use std::ptr;
+
+// D3D12_ENCODE_SHADER_4_COMPONENT_MAPPING(0, 1, 2, 3), missing from winapi
+const D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING: u32 = 0x1688;
+
+// plain 2d views unless the view is of a texture array
+fn is_array_view(first_array_slice: u32, array_size: u32) -> bool {
+    first_array_slice != 0 || array_size > 1
+}
+
+// render pass api of ID3D12GraphicsCommandList4, missing from winapi
+const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_DISCARD: u32 = 0;
+const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_PRESERVE: u32 = 1;
+const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_CLEAR: u32 = 2;
+const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_NO_ACCESS: u32 = 3;
+
+const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_DISCARD: u32 = 0;
+const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_PRESERVE: u32 = 1;
+const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_NO_ACCESS: u32 = 3;
+
+const D3D12_RENDER_PASS_FLAG_ALLOW_UAV_WRITES: u32 = 0x1;
+
+// event data is a null terminated utf-16 string, from pix3_win.h
+const WINPIX_EVENT_UNICODE_VERSION: u32 = 0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct D3D12_RENDER_PASS_BEGINNING_ACCESS {
+    Type: u32,
+    // the only member of a union
+    Clear: D3D12_CLEAR_VALUE
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_PARAMETERS {
+    pSrcResource: *mut ID3D12Resource,
+    pDstResource: *mut ID3D12Resource,
+    SubresourceCount: u32,
+    pSubresourceParameters: *const c_void,
+    Format: DXGI_FORMAT,
+    ResolveMode: u32,
+    PreserveResolveSource: i32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct D3D12_RENDER_PASS_ENDING_ACCESS {
+    Type: u32,
+    // the only member of a union
+    Resolve: D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_PARAMETERS
+}
+
+#[repr(C)]
+struct D3D12_RENDER_PASS_RENDER_TARGET_DESC {
+    cpuDescriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    BeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    EndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS
+}
+
+#[repr(C)]
+struct D3D12_RENDER_PASS_DEPTH_STENCIL_DESC {
+    cpuDescriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    DepthBeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    StencilBeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    DepthEndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS,
+    StencilEndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS
+}
+
+RIDL!{#[uuid(0x38c3e585, 0xff17, 0x412c, 0x91, 0x50, 0x4f, 0xc6, 0xf9, 0xd7, 0x2a, 0x28)]
+interface ID3D12GraphicsCommandList2(ID3D12GraphicsCommandList2Vtbl): ID3D12GraphicsCommandList1(ID3D12GraphicsCommandList1Vtbl) {
+    fn WriteBufferImmediate(Count: u32, pParams: *const c_void, pModes: *const u32,) -> (),
+}}
+
+RIDL!{#[uuid(0x6fda83a7, 0xb84c, 0x4e38, 0x9a, 0xc8, 0xc7, 0xbd, 0x22, 0x01, 0x6b, 0x3d)]
+interface ID3D12GraphicsCommandList3(ID3D12GraphicsCommandList3Vtbl): ID3D12GraphicsCommandList2(ID3D12GraphicsCommandList2Vtbl) {
+    fn SetProtectedResourceSession(pProtectedResourceSession: *mut c_void,) -> (),
+}}
+
+// only the methods up to the ones used are declared
+RIDL!{#[uuid(0x8754318e, 0xd3a9, 0x4541, 0x98, 0xcf, 0x64, 0x5b, 0x50, 0xdc, 0x48, 0x74)]
+interface ID3D12GraphicsCommandList4(ID3D12GraphicsCommandList4Vtbl): ID3D12GraphicsCommandList3(ID3D12GraphicsCommandList3Vtbl) {
+    fn BeginRenderPass(NumRenderTargets: u32, pRenderTargets: *const D3D12_RENDER_PASS_RENDER_TARGET_DESC, pDepthStencil: *const D3D12_RENDER_PASS_DEPTH_STENCIL_DESC, Flags: u32,) -> (),
+    fn EndRenderPass() -> (),
+}}
+
+fn d3d12_clear_value(format: Format, value: ClearValue) -> D3D12_CLEAR_VALUE {
+    let mut clear: D3D12_CLEAR_VALUE = unsafe { ::std::mem::zeroed() };
+    clear.Format = format.into();
+
+    unsafe {
+        match value {
+            ClearValue::Color(color) => *clear.u.Color_mut() = color,
+            ClearValue::DepthStencil(depth, stencil) => *clear.u.DepthStencil_mut() = D3D12_DEPTH_STENCIL_VALUE {
+                Depth: depth,
+                Stencil: stencil
+            }
+        }
+    }
+
+    clear
+}
+
+fn beginning_access(attachment: Option<&RenderPassAttachment>) -> D3D12_RENDER_PASS_BEGINNING_ACCESS {
+    let (ty, clear) = match attachment.map(|attachment| (attachment.format, attachment.load)) {
+        Some((format, LoadOp::Clear(value))) => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_CLEAR, d3d12_clear_value(format, value)),
+        Some((_, LoadOp::Preserve)) => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_PRESERVE, unsafe { ::std::mem::zeroed() }),
+        Some((_, LoadOp::Discard)) => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_DISCARD, unsafe { ::std::mem::zeroed() }),
+        None => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_NO_ACCESS, unsafe { ::std::mem::zeroed() })
+    };
+
+    D3D12_RENDER_PASS_BEGINNING_ACCESS {
+        Type: ty,
+        Clear: clear
+    }
+}
+
+fn ending_access(attachment: Option<&RenderPassAttachment>) -> D3D12_RENDER_PASS_ENDING_ACCESS {
+    D3D12_RENDER_PASS_ENDING_ACCESS {
+        Type: match attachment.map(|attachment| attachment.store) {
+            Some(StoreOp::Preserve) => D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_PRESERVE,
+            Some(StoreOp::Discard) => D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_DISCARD,
+            None => D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_NO_ACCESS
+        },
+        Resolve: unsafe { ::std::mem::zeroed() }
+    }
+}
+
+impl Into<DXGI_FORMAT> for Format {
+    fn into(self) -> DXGI_FORMAT {
+        match self {
+            Format::Unknown => DXGI_FORMAT_UNKNOWN,
+            Format::R8G8B8A8Unorm => DXGI_FORMAT_R8G8B8A8_UNORM,
+            Format::R8Unorm => DXGI_FORMAT_R8_UNORM,
+            Format::R32Typeless => DXGI_FORMAT_R32_TYPELESS,
+            Format::R32Float => DXGI_FORMAT_R32_FLOAT,
+            Format::D32Float => DXGI_FORMAT_D32_FLOAT,
+        }
+    }
+}
+
+impl Into<D3D12_RESOURCE_DIMENSION> for ResourceDimension {
+    fn into(self) -> D3D12_RESOURCE_DIMENSION {
+        match self {
+            ResourceDimension::Buffer => D3D12_RESOURCE_DIMENSION_BUFFER,
+            ResourceDimension::Texture2D => D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        }
+    }
+}
+
+impl Into<D3D12_RESOURCE_DESC> for ResourceDesc {
+    fn into(self) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: self.dimension.into(),
+            Alignment: 0,
+            Width: self.width,
+            Height: self.height,
+            DepthOrArraySize: self.depth_or_array_size,
+            MipLevels: self.mip_levels,
+            Format: self.format.into(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0
+            },
+            Layout: match self.dimension {
+                ResourceDimension::Buffer => D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                _ => D3D12_TEXTURE_LAYOUT_UNKNOWN
+            },
+            Flags: self.flags.bits(),
+        }
+    }
+}
+
+impl Into<D3D12_CPU_DESCRIPTOR_HANDLE> for CpuDescriptorHandle {
+    fn into(self) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+        D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: self.ptr
+        }
+    }
+}
+
+impl Into<D3D12_GPU_DESCRIPTOR_HANDLE> for GpuDescriptorHandle {
+    fn into(self) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        D3D12_GPU_DESCRIPTOR_HANDLE {
+            ptr: self.ptr
+        }
+    }
+}
+
+impl Into<D3D12_RESOURCE_BARRIER> for ResourceBarrier<*mut ID3D12Resource> {
+    fn into(self) -> D3D12_RESOURCE_BARRIER {
+        match self {
+            ResourceBarrier::Transition(resource, subresource, from, to, flags) => {
+                unsafe {
+                    let mut barrier: D3D12_RESOURCE_BARRIER = ::std::mem::zeroed();
+
+                    barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_TRANSITION;
+                    barrier.Flags = flags.bits();
+
+                    (*barrier.u.Transition_mut()) = D3D12_RESOURCE_TRANSITION_BARRIER {
+                        pResource: resource,
+                        Subresource: subresource,
+                        StateBefore: from.bits(),
+                        StateAfter: to.bits(),
+                    };
+
+                    barrier
+                }
+            },
+            ResourceBarrier::Alias(before, after) => {
+                unsafe {
+                    let mut barrier: D3D12_RESOURCE_BARRIER = ::std::mem::zeroed();
+
+                    barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_ALIASING;
+                    barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+
+                    (*barrier.u.Aliasing_mut()) = D3D12_RESOURCE_ALIASING_BARRIER {
+                        pResourceBefore: before.unwrap_or(ptr::null_mut()),
+                        pResourceAfter: after
+                    };
+
+                    barrier
+                }
+            },
+            ResourceBarrier::Uav(resource) => {
+                unsafe {
+                    let mut barrier: D3D12_RESOURCE_BARRIER = ::std::mem::zeroed();
+
+                    barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_UAV;
+                    barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+
+                    (*barrier.u.UAV_mut()) = D3D12_RESOURCE_UAV_BARRIER {
+                        pResource: resource
+                    };
+
+                    barrier
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+// a queue together with the fence the frame graph's queue plan signals and
+// waits on, and a pool of command lists recycled once the GPU is done with them
+struct CommandQueue {
+    queue: *mut ID3D12CommandQueue,
+    fence: *mut ID3D12Fence,
+
+    // signalled after every submission, independent of the queue plan
+    pool_fence: *mut ID3D12Fence,
+    pool_value: u64,
+    // (pool fence value the list is free at, allocator, list)
+    pool: Vec<(u64, *mut ID3D12CommandAllocator, *mut ID3D12GraphicsCommandList)>
+}
+
+impl CommandQueue {
+    unsafe fn new(device: *mut ID3D12Device, queue: *mut ID3D12CommandQueue) -> Self {
+        let mut fence: *mut ID3D12Fence = ptr::null_mut();
+        let mut pool_fence: *mut ID3D12Fence = ptr::null_mut();
+
+        (*device).CreateFence(0, D3D12_FENCE_FLAG_NONE, &ID3D12Fence::uuidof(), &mut fence as *mut *mut _ as *mut *mut _);
+        (*device).CreateFence(0, D3D12_FENCE_FLAG_NONE, &ID3D12Fence::uuidof(), &mut pool_fence as *mut *mut _ as *mut *mut _);
+
+        CommandQueue {
+            queue,
+            fence,
+            pool_fence,
+            pool_value: 0,
+            pool: Vec::new()
+        }
+    }
+}
+
+impl Into<D3D12_COMMAND_LIST_TYPE> for QueueType {
+    fn into(self) -> D3D12_COMMAND_LIST_TYPE {
+        match self {
+            QueueType::Graphics => D3D12_COMMAND_LIST_TYPE_DIRECT,
+            QueueType::Compute => D3D12_COMMAND_LIST_TYPE_COMPUTE,
+            QueueType::Copy => D3D12_COMMAND_LIST_TYPE_COPY,
+        }
+    }
+}
+
+pub struct D3D12Backend {
+    device: *mut ID3D12Device,
+
+    // compute and copy queues are only created once something runs on them
+    queues: [Option<CommandQueue>; 3],
+
+    cbv_srv_uav_heap: *mut ID3D12DescriptorHeap,
+    srv_stride: u32,
+
+    rtv_heap: *mut ID3D12DescriptorHeap,
+    rtv_stride: u32,
+
+    dsv_heap: *mut ID3D12DescriptorHeap,
+    dsv_stride: u32,
+
+    // null until the profiler asks for timestamps
+    query_heap: *mut ID3D12QueryHeap,
+    query_readback: *mut ID3D12Resource,
+
+    // released heaps and resources the submitted lists may still use, with
+    // the pool fence value of every queue they're free at
+    retired: Vec<([u64; 3], *mut IUnknown)>,
+}
+
+impl D3D12Backend {
+    // `queue` is the direct queue the swapchain presents from
+    pub fn new(device: *mut ID3D12Device, queue: *mut ID3D12CommandQueue) -> Self {
+        let (gpu_heap, gpu_stride, cpu_heap, cpu_stride, dsv_heap, dsv_stride) = unsafe {
+            let mut gpu_heap: *mut ID3D12DescriptorHeap = ptr::null_mut();
+            let mut cpu_heap: *mut ID3D12DescriptorHeap = ptr::null_mut();
+            let mut dsv_heap: *mut ID3D12DescriptorHeap = ptr::null_mut();
+
+            // passes get gpu handles into it, so it has to be visible to shaders
+            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
+                NumDescriptors: 3000,
+                Type: D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+                Flags: D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+                NodeMask: 0
+            };
+
+            (*device).CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::uuidof(), &mut gpu_heap as *mut *mut _ as *mut *mut _);
+
+            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
+                NumDescriptors: 3000,
+                Type: D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+                Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+                NodeMask: 0
+            };
+
+            (*device).CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::uuidof(), &mut cpu_heap as *mut *mut _ as *mut *mut _);
+
+            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
+                NumDescriptors: 3000,
+                Type: D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
+                Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+                NodeMask: 0
+            };
+
+            (*device).CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::uuidof(), &mut dsv_heap as *mut *mut _ as *mut *mut _);
+
+            let gpu_stride = (*device).GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
+            let cpu_stride = (*device).GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_RTV);
+            let dsv_stride = (*device).GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_DSV);
+
+            (gpu_heap, gpu_stride, cpu_heap, cpu_stride, dsv_heap, dsv_stride)
+        };
+
+        D3D12Backend {
+            device: device,
+
+            queues: [Some(unsafe { CommandQueue::new(device, queue) }), None, None],
+
+            cbv_srv_uav_heap: gpu_heap,
+            srv_stride: gpu_stride,
+
+            rtv_heap: cpu_heap,
+            rtv_stride: cpu_stride,
+
+            dsv_heap: dsv_heap,
+            dsv_stride: dsv_stride,
+
+            query_heap: ptr::null_mut(),
+            query_readback: ptr::null_mut(),
+
+            retired: Vec::new(),
+        }
+    }
+
+    fn command_queue(&mut self, queue: QueueType) -> &mut CommandQueue {
+        let device = self.device;
+        let slot = &mut self.queues[queue.index()];
+
+        if slot.is_none() {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: queue.into(),
+                Priority: 0,
+                Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0
+            };
+
+            unsafe {
+                let mut command_queue: *mut ID3D12CommandQueue = ptr::null_mut();
+                (*device).CreateCommandQueue(&desc, &ID3D12CommandQueue::uuidof(), &mut command_queue as *mut *mut _ as *mut *mut _);
+
+                *slot = Some(CommandQueue::new(device, command_queue));
+            }
+        }
+
+        slot.as_mut().unwrap()
+    }
+
+    // blocks until every list submitted so far finished executing
+    fn wait_for_submissions(&self) {
+        unsafe {
+            let event = CreateEventW(ptr::null_mut(), FALSE, FALSE, ptr::null_mut());
+
+            for command_queue in self.queues.iter().flatten() {
+                if (*command_queue.pool_fence).GetCompletedValue() < command_queue.pool_value {
+                    (*command_queue.pool_fence).SetEventOnCompletion(command_queue.pool_value, event);
+                    WaitForSingleObject(event, INFINITE);
+                }
+            }
+
+            CloseHandle(event);
+        }
+    }
+
+    // releases `object` once every list submitted so far finished executing
+    fn retire(&mut self, object: *mut IUnknown) {
+        let mut submitted = [0; 3];
+
+        for (value, command_queue) in submitted.iter_mut().zip(&self.queues) {
+            if let Some(ref command_queue) = *command_queue {
+                *value = command_queue.pool_value;
+            }
+        }
+
+        self.retired.push((submitted, object));
+    }
+
+    fn release_retired(&mut self) {
+        let mut completed = [u64::max_value(); 3];
+
+        for (value, command_queue) in completed.iter_mut().zip(&self.queues) {
+            if let Some(ref command_queue) = *command_queue {
+                *value = unsafe { (*command_queue.pool_fence).GetCompletedValue() };
+            }
+        }
+
+        // in release order, so resources go before the heaps they're placed in
+        self.retired.retain(|&(submitted, object)| {
+            let done = submitted.iter().zip(&completed).all(|(submitted, completed)| submitted <= completed);
+            if done {
+                unsafe { (*object).Release(); }
+            }
+
+            !done
+        });
+    }
+
+    // cpu side of the cbv/srv/uav heap, views are written through this
+    fn srv_cpu_handle(&self, view_id: u32) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+        let mut handle = unsafe { (*self.cbv_srv_uav_heap).GetCPUDescriptorHandleForHeapStart() };
+        handle.ptr += view_id as usize * self.srv_stride as usize;
+
+        handle
+    }
+}
+
+// the device and descriptor heaps are free-threaded and the `&self` methods
+// only record into the command list they're given, which the frame graph never
+// hands to two threads at once
+unsafe impl Sync for D3D12Backend {}
+
+impl Backend for D3D12Backend {
+    type Heap = *mut ID3D12Heap;
+    type Resource = *mut ID3D12Resource;
+    type CommandList = *mut ID3D12GraphicsCommandList;
+
+    fn begin_commands(&mut self, queue: QueueType) -> *mut ID3D12GraphicsCommandList {
+        self.release_retired();
+
+        let device = self.device;
+        let heap = self.cbv_srv_uav_heap;
+        let command_queue = self.command_queue(queue);
+
+        // copy lists can't bind descriptor heaps
+        let bind_heaps = |list: *mut ID3D12GraphicsCommandList| if queue != QueueType::Copy {
+            let mut heaps = [heap];
+
+            unsafe { (*list).SetDescriptorHeaps(1, heaps.as_mut_ptr()); }
+        };
+
+        unsafe {
+            let completed = (*command_queue.pool_fence).GetCompletedValue();
+
+            if let Some(entry) = command_queue.pool.iter_mut().find(|entry| entry.0 <= completed) {
+                // in use until it is submitted
+                entry.0 = u64::max_value();
+
+                (*entry.1).Reset();
+                (*entry.2).Reset(entry.1, ptr::null_mut());
+                bind_heaps(entry.2);
+
+                return entry.2;
+            }
+
+            let mut allocator: *mut ID3D12CommandAllocator = ptr::null_mut();
+            (*device).CreateCommandAllocator(queue.into(), &ID3D12CommandAllocator::uuidof(), &mut allocator as *mut *mut _ as *mut *mut _);
+
+            let mut list: *mut ID3D12GraphicsCommandList = ptr::null_mut();
+            (*device).CreateCommandList(0, queue.into(), allocator, ptr::null_mut(), &ID3D12GraphicsCommandList::uuidof(), &mut list as *mut *mut _ as *mut *mut _);
+
+            command_queue.pool.push((u64::max_value(), allocator, list));
+            bind_heaps(list);
+
+            list
+        }
+    }
+
+    fn submit_commands(&mut self, queue: QueueType, list: *mut ID3D12GraphicsCommandList, waits: &[(QueueType, u64)], signal: Option<u64>) {
+        let waits = waits.iter().map(|&(wait_queue, value)| (self.command_queue(wait_queue).fence, value)).collect::<Vec<_>>();
+        let command_queue = self.command_queue(queue);
+
+        unsafe {
+            (*list).Close();
+
+            for &(fence, value) in &waits {
+                (*command_queue.queue).Wait(fence, value);
+            }
+
+            (*command_queue.queue).ExecuteCommandLists(1, &list as *const *mut _ as *const *mut _);
+
+            if let Some(value) = signal {
+                (*command_queue.queue).Signal(command_queue.fence, value);
+            }
+
+            command_queue.pool_value += 1;
+            (*command_queue.queue).Signal(command_queue.pool_fence, command_queue.pool_value);
+
+            let pool_value = command_queue.pool_value;
+            if let Some(entry) = command_queue.pool.iter_mut().find(|entry| entry.2 == list) {
+                entry.0 = pool_value;
+            }
+        }
+    }
+
+    fn resource_allocation_info(&self, desc: &ResourceDesc) -> (u64, u64) {
+        let desc: D3D12_RESOURCE_DESC = (*desc).into();
+
+        unsafe {
+            let alloc_info = (*self.device).GetResourceAllocationInfo(0, 1, &desc as *const _);
+
+            (alloc_info.SizeInBytes, alloc_info.Alignment)
+        }
+    }
+
+    fn create_heap(&mut self, size: u64, flags: HeapFlags) -> *mut ID3D12Heap {
+        unsafe {
+            let mut heap_ptr: *mut ID3D12Heap = ::std::mem::zeroed();
+
+            let desc = D3D12_HEAP_DESC {
+                SizeInBytes: size,
+                Properties: D3D12_HEAP_PROPERTIES {
+                    Type: D3D12_HEAP_TYPE_DEFAULT,
+                    CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+                    MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+                    CreationNodeMask: 0,
+                    VisibleNodeMask: 0,
+                },
+                Alignment: 0,
+                Flags: flags.bits()
+            };
+
+            (*self.device).CreateHeap(&desc, &ID3D12Heap::uuidof(), &mut heap_ptr as *mut *mut _ as *mut *mut _);
+
+            heap_ptr
+        }
+    }
+
+    fn release_heap(&mut self, heap: *mut ID3D12Heap) {
+        self.retire(heap as *mut IUnknown);
+    }
+
+    fn create_placed_resource(&mut self, heap: *mut ID3D12Heap, offset: u64, desc: &ResourceDesc, state: ResourceStates, clear_value: Option<ClearValue>, name: &str) -> *mut ID3D12Resource {
+        let clear_value = clear_value.map(|value| d3d12_clear_value(desc.format, value));
+
+        // the clear value keeps the depth format
+        let desc: D3D12_RESOURCE_DESC = ResourceDesc { format: desc.format.typeless(), ..*desc }.into();
+        let mut resource: *mut ID3D12Resource = ptr::null_mut();
+
+        unsafe {
+            let clear_value = clear_value.as_ref().map_or(ptr::null(), |clear| clear as *const _);
+
+            (*self.device).CreatePlacedResource(heap, offset, &desc, state.bits(), clear_value, &ID3D12Resource::uuidof(), &mut resource as *mut *mut _ as *mut *mut _);
+            let name: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0).into_iter()).collect::<Vec<u16>>();
+            (*resource).SetName(name.as_ptr());
+        }
+
+        resource
+    }
+
+    fn release_resource(&mut self, resource: *mut ID3D12Resource) {
+        self.retire(resource as *mut IUnknown);
+    }
+
+    fn create_view(&mut self, resource: *mut ID3D12Resource, view_id: u32, desc: &ResourceViewDesc) {
+        match *desc {
+            ResourceViewDesc::RenderTarget { format, mip_slice, first_array_slice, array_size } => {
+                let mut view_desc: D3D12_RENDER_TARGET_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                if is_array_view(first_array_slice, array_size) {
+                    view_desc.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2DARRAY;
+                    unsafe {
+                        (*view_desc.u.Texture2DArray_mut()) = D3D12_TEX2D_ARRAY_RTV {
+                            MipSlice: mip_slice,
+                            FirstArraySlice: first_array_slice,
+                            ArraySize: array_size,
+                            PlaneSlice: 0
+                        };
+                    }
+                } else {
+                    view_desc.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2D;
+                    unsafe {
+                        (*view_desc.u.Texture2D_mut()) = D3D12_TEX2D_RTV {
+                            MipSlice: mip_slice,
+                            PlaneSlice: 0
+                        };
+                    }
+                }
+
+                let handle = self.cpu_handle(view_id).into();
+
+                unsafe {
+                    (*self.device).CreateRenderTargetView(resource, &view_desc, handle);
+                }
+            },
+            ResourceViewDesc::ShaderResource { format, most_detailed_mip, mip_levels, first_array_slice, array_size } => {
+                let mut view_desc: D3D12_SHADER_RESOURCE_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                view_desc.Shader4ComponentMapping = D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING;
+                // `ALL_MIPS` is the -1 d3d12 expects
+                if is_array_view(first_array_slice, array_size) {
+                    view_desc.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2DARRAY;
+                    unsafe {
+                        (*view_desc.u.Texture2DArray_mut()) = D3D12_TEX2D_ARRAY_SRV {
+                            MostDetailedMip: most_detailed_mip,
+                            MipLevels: mip_levels,
+                            FirstArraySlice: first_array_slice,
+                            ArraySize: array_size,
+                            PlaneSlice: 0,
+                            ResourceMinLODClamp: 0.0
+                        };
+                    }
+                } else {
+                    view_desc.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2D;
+                    unsafe {
+                        (*view_desc.u.Texture2D_mut()) = D3D12_TEX2D_SRV {
+                            MostDetailedMip: most_detailed_mip,
+                            MipLevels: mip_levels,
+                            PlaneSlice: 0,
+                            ResourceMinLODClamp: 0.0
+                        };
+                    }
+                }
+
+                let handle = self.srv_cpu_handle(view_id);
+
+                unsafe {
+                    (*self.device).CreateShaderResourceView(resource, &view_desc, handle);
+                }
+            },
+            ResourceViewDesc::UnorderedAccess { format, mip_slice, first_array_slice, array_size } => {
+                let mut view_desc: D3D12_UNORDERED_ACCESS_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                if is_array_view(first_array_slice, array_size) {
+                    view_desc.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE2DARRAY;
+                    unsafe {
+                        (*view_desc.u.Texture2DArray_mut()) = D3D12_TEX2D_ARRAY_UAV {
+                            MipSlice: mip_slice,
+                            FirstArraySlice: first_array_slice,
+                            ArraySize: array_size,
+                            PlaneSlice: 0
+                        };
+                    }
+                } else {
+                    view_desc.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE2D;
+                    unsafe {
+                        (*view_desc.u.Texture2D_mut()) = D3D12_TEX2D_UAV {
+                            MipSlice: mip_slice,
+                            PlaneSlice: 0
+                        };
+                    }
+                }
+
+                let handle = self.srv_cpu_handle(view_id);
+
+                unsafe {
+                    (*self.device).CreateUnorderedAccessView(resource, ptr::null_mut(), &view_desc, handle);
+                }
+            },
+            ResourceViewDesc::DepthStencil { format, mip_slice, first_array_slice, array_size, read_only } => {
+                let mut view_desc: D3D12_DEPTH_STENCIL_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                // none of the depth formats carry stencil yet
+                view_desc.Flags = if read_only { D3D12_DSV_FLAG_READ_ONLY_DEPTH } else { D3D12_DSV_FLAG_NONE };
+                if is_array_view(first_array_slice, array_size) {
+                    view_desc.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2DARRAY;
+                    unsafe {
+                        (*view_desc.u.Texture2DArray_mut()) = D3D12_TEX2D_ARRAY_DSV {
+                            MipSlice: mip_slice,
+                            FirstArraySlice: first_array_slice,
+                            ArraySize: array_size
+                        };
+                    }
+                } else {
+                    view_desc.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2D;
+                    unsafe {
+                        (*view_desc.u.Texture2D_mut()) = D3D12_TEX2D_DSV {
+                            MipSlice: mip_slice
+                        };
+                    }
+                }
+
+                let handle = self.dsv_handle(view_id).into();
+
+                unsafe {
+                    (*self.device).CreateDepthStencilView(resource, &view_desc, handle);
+                }
+            },
+            ResourceViewDesc::BufferShaderResource { format, num_elements, stride, raw } => {
+                let mut view_desc: D3D12_SHADER_RESOURCE_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                view_desc.ViewDimension = D3D12_SRV_DIMENSION_BUFFER;
+                view_desc.Shader4ComponentMapping = D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING;
+                unsafe {
+                    (*view_desc.u.Buffer_mut()) = D3D12_BUFFER_SRV {
+                        FirstElement: 0,
+                        NumElements: num_elements,
+                        StructureByteStride: stride,
+                        Flags: if raw { D3D12_BUFFER_SRV_FLAG_RAW } else { D3D12_BUFFER_SRV_FLAG_NONE }
+                    };
+                }
+
+                let handle = self.srv_cpu_handle(view_id);
+
+                unsafe {
+                    (*self.device).CreateShaderResourceView(resource, &view_desc, handle);
+                }
+            },
+            ResourceViewDesc::BufferUnorderedAccess { format, num_elements, stride, raw } => {
+                let mut view_desc: D3D12_UNORDERED_ACCESS_VIEW_DESC = unsafe { ::std::mem::zeroed() };
+                view_desc.Format = format.into();
+                view_desc.ViewDimension = D3D12_UAV_DIMENSION_BUFFER;
+                unsafe {
+                    (*view_desc.u.Buffer_mut()) = D3D12_BUFFER_UAV {
+                        FirstElement: 0,
+                        NumElements: num_elements,
+                        StructureByteStride: stride,
+                        CounterOffsetInBytes: 0,
+                        Flags: if raw { D3D12_BUFFER_UAV_FLAG_RAW } else { D3D12_BUFFER_UAV_FLAG_NONE }
+                    };
+                }
+
+                let handle = self.srv_cpu_handle(view_id);
+
+                unsafe {
+                    (*self.device).CreateUnorderedAccessView(resource, ptr::null_mut(), &view_desc, handle);
+                }
+            }
+        }
+    }
+
+    fn cpu_handle(&self, view_id: u32) -> CpuDescriptorHandle {
+        let handle = unsafe { (*self.rtv_heap).GetCPUDescriptorHandleForHeapStart() };
+
+        CpuDescriptorHandle {
+            ptr: handle.ptr + view_id as usize * self.rtv_stride as usize
+        }
+    }
+
+    fn gpu_handle(&self, view_id: u32) -> GpuDescriptorHandle {
+        let handle = unsafe { (*self.cbv_srv_uav_heap).GetGPUDescriptorHandleForHeapStart() };
+
+        GpuDescriptorHandle {
+            ptr: handle.ptr + view_id as u64 * self.srv_stride as u64
+        }
+    }
+
+    fn dsv_handle(&self, view_id: u32) -> CpuDescriptorHandle {
+        let handle = unsafe { (*self.dsv_heap).GetCPUDescriptorHandleForHeapStart() };
+
+        CpuDescriptorHandle {
+            ptr: handle.ptr + view_id as usize * self.dsv_stride as usize
+        }
+    }
+
+    fn resource_barrier(&self, list: *mut ID3D12GraphicsCommandList, barriers: &[ResourceBarrier<*mut ID3D12Resource>]) {
+        let barriers = barriers.iter().map(|&barrier| barrier.into()).collect::<Vec<D3D12_RESOURCE_BARRIER>>();
+
+        unsafe { (*list).ResourceBarrier(barriers.len() as u32, barriers.as_ptr()); }
+    }
+
+    fn clear_render_target(&self, list: *mut ID3D12GraphicsCommandList, view_id: u32, color: [f32; 4]) {
+        let handle = self.cpu_handle(view_id).into();
+
+        unsafe { (*list).ClearRenderTargetView(handle, &color, 0, ptr::null()); }
+    }
+
+    fn clear_depth_stencil(&self, list: *mut ID3D12GraphicsCommandList, view_id: u32, depth: f32, stencil: u8) {
+        let handle = self.dsv_handle(view_id).into();
+
+        // none of the depth formats carry stencil yet
+        unsafe { (*list).ClearDepthStencilView(handle, D3D12_CLEAR_FLAG_DEPTH, depth, stencil, 0, ptr::null()); }
+    }
+
+    fn discard_resource(&self, list: *mut ID3D12GraphicsCommandList, resource: *mut ID3D12Resource) {
+        unsafe { (*list).DiscardResource(resource, ptr::null()); }
+    }
+
+    fn begin_render_pass(&self, list: *mut ID3D12GraphicsCommandList, render_targets: &[RenderPassAttachment], depth: Option<RenderPassAttachment>) {
+        let render_targets = render_targets.iter().map(|render_target| D3D12_RENDER_PASS_RENDER_TARGET_DESC {
+            cpuDescriptor: self.cpu_handle(render_target.view_id).into(),
+            BeginningAccess: beginning_access(Some(render_target)),
+            EndingAccess: ending_access(Some(render_target))
+        }).collect::<Vec<_>>();
+
+        // none of the depth formats carry stencil yet
+        let depth = depth.map(|depth| D3D12_RENDER_PASS_DEPTH_STENCIL_DESC {
+            cpuDescriptor: self.dsv_handle(depth.view_id).into(),
+            DepthBeginningAccess: beginning_access(Some(&depth)),
+            StencilBeginningAccess: beginning_access(None),
+            DepthEndingAccess: ending_access(Some(&depth)),
+            StencilEndingAccess: ending_access(None)
+        });
+
+        unsafe {
+            let mut list4: *mut ID3D12GraphicsCommandList4 = ptr::null_mut();
+            (*list).QueryInterface(&ID3D12GraphicsCommandList4::uuidof(), &mut list4 as *mut *mut _ as *mut *mut _);
+
+            (*list4).BeginRenderPass(
+                render_targets.len() as u32,
+                render_targets.as_ptr(),
+                depth.as_ref().map_or(ptr::null(), |depth| depth as *const _),
+                D3D12_RENDER_PASS_FLAG_ALLOW_UAV_WRITES
+            );
+            (*list4).Release();
+        }
+    }
+
+    fn end_render_pass(&self, list: *mut ID3D12GraphicsCommandList) {
+        unsafe {
+            let mut list4: *mut ID3D12GraphicsCommandList4 = ptr::null_mut();
+            (*list).QueryInterface(&ID3D12GraphicsCommandList4::uuidof(), &mut list4 as *mut *mut _ as *mut *mut _);
+
+            (*list4).EndRenderPass();
+            (*list4).Release();
+        }
+    }
+
+    fn create_timestamp_queries(&mut self, count: u32) {
+        // the frames still in flight write and resolve into the old ones, this
+        // only happens when the profiler needs more room, so waiting is fine
+        if !self.query_heap.is_null() {
+            self.wait_for_submissions();
+        }
+
+        unsafe {
+            if !self.query_heap.is_null() {
+                (*self.query_heap).Release();
+                (*self.query_readback).Release();
+            }
+
+            let desc = D3D12_QUERY_HEAP_DESC {
+                Type: D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+                Count: count,
+                NodeMask: 0
+            };
+
+            (*self.device).CreateQueryHeap(&desc, &ID3D12QueryHeap::uuidof(), &mut self.query_heap as *mut *mut _ as *mut *mut _);
+
+            let properties = D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_READBACK,
+                CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+                MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+                CreationNodeMask: 0,
+                VisibleNodeMask: 0,
+            };
+
+            let desc: D3D12_RESOURCE_DESC = ResourceDesc {
+                dimension: ResourceDimension::Buffer,
+                width: count as u64 * 8,
+                height: 1,
+                depth_or_array_size: 1,
+                mip_levels: 1,
+                format: Format::Unknown,
+                flags: ResourceFlags::empty()
+            }.into();
+
+            (*self.device).CreateCommittedResource(&properties, D3D12_HEAP_FLAG_NONE, &desc, D3D12_RESOURCE_STATE_COPY_DEST, ptr::null(), &ID3D12Resource::uuidof(), &mut self.query_readback as *mut *mut _ as *mut *mut _);
+        }
+    }
+
+    fn write_timestamp(&self, list: *mut ID3D12GraphicsCommandList, index: u32) {
+        unsafe { (*list).EndQuery(self.query_heap, D3D12_QUERY_TYPE_TIMESTAMP, index); }
+    }
+
+    fn resolve_timestamps(&self, list: *mut ID3D12GraphicsCommandList, first: u32, count: u32) {
+        unsafe { (*list).ResolveQueryData(self.query_heap, D3D12_QUERY_TYPE_TIMESTAMP, first, count, self.query_readback, first as u64 * 8); }
+    }
+
+    fn read_timestamps(&self, first: u32, timestamps: &mut [u64]) {
+        let range = D3D12_RANGE {
+            Begin: first as usize * 8,
+            End: (first as usize + timestamps.len()) * 8
+        };
+        // nothing is written
+        let written = D3D12_RANGE {
+            Begin: 0,
+            End: 0
+        };
+
+        unsafe {
+            let mut data: *mut c_void = ptr::null_mut();
+            (*self.query_readback).Map(0, &range, &mut data);
+
+            ptr::copy_nonoverlapping((data as *const u64).offset(first as isize), timestamps.as_mut_ptr(), timestamps.len());
+            (*self.query_readback).Unmap(0, &written);
+        }
+    }
+
+    fn begin_event(&self, list: *mut ID3D12GraphicsCommandList, name: &str) {
+        let name: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0).into_iter()).collect::<Vec<u16>>();
+
+        unsafe { (*list).BeginEvent(WINPIX_EVENT_UNICODE_VERSION, name.as_ptr() as *const _, (name.len() * 2) as u32); }
+    }
+
+    fn end_event(&self, list: *mut ID3D12GraphicsCommandList) {
+        unsafe { (*list).EndEvent(); }
+    }
+
+    // every queue is assumed to tick at the rate of the direct queue
+    fn timestamp_frequency(&self) -> u64 {
+        let queue = self.queues[0].as_ref().unwrap().queue;
+        let mut frequency = 0;
+
+        unsafe { (*queue).GetTimestampFrequency(&mut frequency); }
+
+        frequency
+    }
+}
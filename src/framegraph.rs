@@ -1,11 +1,47 @@
-use winapi::um::d3d12::*;
+use backend::{
+    Backend,
+    BarrierFlags,
+    ClearValue,
+    CpuDescriptorHandle,
+    Format,
+    GpuDescriptorHandle,
+    LoadOp,
+    QueueType,
+    RenderPassAttachment,
+    ResourceBarrier,
+    ResourceDesc,
+    ResourceDimension,
+    ResourceFlags,
+    ResourceStates,
+    ResourceViewDesc,
+    StoreOp,
+    ALL_MIPS,
+    ALL_SUBRESOURCES
+};
+
+use alloc::HeapMemoryAllocator;
+
+use profiler::{
+    CompileTimings,
+    FrameTimings,
+    PassTiming,
+    Profiler
+};
 
-use winapi::shared::dxgiformat::*;
-use winapi::shared::dxgitype::*;
+use schedule::{
+    order_passes,
+    peak_memory,
+    plan_queues,
+    OrderPass,
+    QueuePass,
+    QueuePlan,
+    ScheduleStrategy
+};
 
-use alloc::{
-    HeapMemoryAllocator,
-    HeapMemoryCacheEntry
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    Instant
 };
 
 bitflags! {
@@ -14,6 +50,7 @@ bitflags! {
         const SHADER_RESOURCE = 0x2;
         const DEPTH_WRITE = 0x4;
         const DEPTH_READ = 0x8;
+        const UNORDERED_ACCESS = 0x10;
     }
 }
 
@@ -23,66 +60,85 @@ impl TransitionFlags {
     }
 
     fn has_write(self) -> bool {
-        self.intersects(TransitionFlags::RENDER_TARGET | TransitionFlags::DEPTH_WRITE)
+        self.intersects(TransitionFlags::RENDER_TARGET | TransitionFlags::DEPTH_WRITE | TransitionFlags::UNORDERED_ACCESS)
     }
 
-    fn into_resource_state(self) -> D3D12_RESOURCE_STATES {
-        let mut out = D3D12_RESOURCE_FLAG_NONE;
+    fn into_resource_state(self) -> ResourceStates {
+        let mut out = ResourceStates::empty();
 
         if self.contains(TransitionFlags::RENDER_TARGET) {
-            out |= D3D12_RESOURCE_STATE_RENDER_TARGET;
+            out |= ResourceStates::RENDER_TARGET;
         }
 
         if self.contains(TransitionFlags::DEPTH_WRITE) {
-            out |= D3D12_RESOURCE_STATE_DEPTH_WRITE;
+            out |= ResourceStates::DEPTH_WRITE;
         }
 
         if self.contains(TransitionFlags::SHADER_RESOURCE) {
-            out |= D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE;
+            // readable from both raster and compute passes
+            out |= ResourceStates::PIXEL_SHADER_RESOURCE | ResourceStates::NON_PIXEL_SHADER_RESOURCE;
         }
 
         if self.contains(TransitionFlags::DEPTH_READ) {
-            out |= D3D12_RESOURCE_STATE_DEPTH_READ;
+            out |= ResourceStates::DEPTH_READ;
+        }
+
+        if self.contains(TransitionFlags::UNORDERED_ACCESS) {
+            out |= ResourceStates::UNORDERED_ACCESS;
         }
 
         out
     }
 
-    fn into_resource_flags(self) -> D3D12_RESOURCE_FLAGS {
-        let mut out = D3D12_RESOURCE_FLAG_NONE;
+    // compute and copy queues can't see pixel shader resources
+    fn into_queue_state(self, queue: QueueType) -> ResourceStates {
+        match queue {
+            QueueType::Graphics => self.into_resource_state(),
+            _ => self.into_resource_state() - ResourceStates::PIXEL_SHADER_RESOURCE
+        }
+    }
+
+    fn into_resource_flags(self) -> ResourceFlags {
+        let mut out = ResourceFlags::empty();
 
         if self.contains(TransitionFlags::RENDER_TARGET) {
-            out |= D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET;
+            out |= ResourceFlags::ALLOW_RENDER_TARGET;
         }
 
         if self.contains(TransitionFlags::DEPTH_WRITE) {
-            out |= D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL;
+            out |= ResourceFlags::ALLOW_DEPTH_STENCIL;
+        }
+
+        if self.contains(TransitionFlags::UNORDERED_ACCESS) {
+            out |= ResourceFlags::ALLOW_UNORDERED_ACCESS;
         }
 
         out
     }
 }
 
-#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+// cloned into every handle, the name is shared
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct FrameGraphResource {
-    name: &'static str,
+    name: Arc<str>,
+    format: Format,
+    buffer: Option<BufferDesc>,
+    // views of a subresource range need to know how many slices "all" is
+    array_size: u32,
     view_id: u32,
-    resource_id: u32
+    resource_id: u32,
+    // ids are reused every frame, handles of earlier frames are rejected
+    frame: u32
 }
 
 pub trait ResourceBinding {
     type PhysicalResource;
 
-    fn get_virtual_resource(&self) -> FrameGraphResource;
-    fn get_virtual_resources(&self) -> Box<[FrameGraphResource]> {
-        Box::new([self.get_virtual_resource()])
-    }
+    fn get_virtual_resources(&self) -> Vec<FrameGraphResource>;
 
-    // TODO: TEMP
-    fn is_cpu(&self) -> bool;
-    fn is_cpus(&self) -> Box<[bool]> {
-        Box::new([self.is_cpu()])
-    }
+    // builds what the exec closure receives, `resources` yields the ones
+    // `get_virtual_resources` returned, in the same order
+    fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> Self::PhysicalResource;
 }
 
 pub trait IntoTypedResource<T> {
@@ -94,14 +150,29 @@ macro_rules! physical_resource_bind {
         pub struct $name(FrameGraphResource);
 
         impl ResourceBinding for $name {
-            type PhysicalResource = D3D12_CPU_DESCRIPTOR_HANDLE;
+            type PhysicalResource = CpuDescriptorHandle;
 
-            fn get_virtual_resource(&self) -> FrameGraphResource {
-                self.0
+            fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+                vec![self.0.clone()]
+            }
+
+            fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> CpuDescriptorHandle {
+                backend.cpu_handle(resources.next().unwrap().view_id)
+            }
+        }
+    };
+    ($name:ident => DSV) => {
+        pub struct $name(FrameGraphResource);
+
+        impl ResourceBinding for $name {
+            type PhysicalResource = CpuDescriptorHandle;
+
+            fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+                vec![self.0.clone()]
             }
 
-            fn is_cpu(&self) -> bool {
-                true
+            fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> CpuDescriptorHandle {
+                backend.dsv_handle(resources.next().unwrap().view_id)
             }
         }
     };
@@ -109,14 +180,14 @@ macro_rules! physical_resource_bind {
         pub struct $name(FrameGraphResource);
 
         impl ResourceBinding for $name {
-            type PhysicalResource = D3D12_GPU_DESCRIPTOR_HANDLE;
+            type PhysicalResource = GpuDescriptorHandle;
 
-            fn get_virtual_resource(&self) -> FrameGraphResource {
-                self.0
+            fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+                vec![self.0.clone()]
             }
 
-            fn is_cpu(&self) -> bool {
-                false
+            fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> GpuDescriptorHandle {
+                backend.gpu_handle(resources.next().unwrap().view_id)
             }
         }
     };
@@ -126,7 +197,7 @@ macro_rules! typed_resource_transition {
     ($type:ty => $target:ty) => {
         impl IntoTypedResource<$target> for $type {
             fn get_virtual_resource(&self) -> FrameGraphResource {
-                self.0
+                self.0.clone()
             }
         }
     }
@@ -135,89 +206,430 @@ macro_rules! typed_resource_transition {
 impl ResourceBinding for () {
     type PhysicalResource = ();
 
-    fn get_virtual_resource(&self) -> FrameGraphResource {
-        unimplemented!()
+    fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+        Vec::new()
     }
 
-    fn is_cpu(&self) -> bool {
-        unimplemented!()
+    fn resolve<B: Backend>(_: &B, _: &mut ::std::slice::Iter<FrameGraphResource>) {}
+}
+
+// several bindings out of one pass, e.g. the targets of a G-buffer
+macro_rules! tuple_binding {
+    ($($idx:tt $name:ident),+) => {
+        impl<$($name: ResourceBinding),+> ResourceBinding for ($($name,)+) {
+            type PhysicalResource = ($($name::PhysicalResource,)+);
+
+            fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+                let mut resources = Vec::new();
+                $(resources.extend(self.$idx.get_virtual_resources());)+
+
+                resources
+            }
+
+            fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> Self::PhysicalResource {
+                ($($name::resolve(backend, resources),)+)
+            }
+        }
     }
 }
 
+macro_rules! array_binding {
+    ($($len:expr => ($($idx:tt)+)),+) => {
+        $(
+            impl<T: ResourceBinding> ResourceBinding for [T; $len] {
+                type PhysicalResource = [T::PhysicalResource; $len];
+
+                fn get_virtual_resources(&self) -> Vec<FrameGraphResource> {
+                    self.iter().flat_map(|binding| binding.get_virtual_resources()).collect()
+                }
+
+                fn resolve<B: Backend>(backend: &B, resources: &mut ::std::slice::Iter<FrameGraphResource>) -> Self::PhysicalResource {
+                    [$(array_binding!(@resolve $idx, backend, resources)),+]
+                }
+            }
+        )+
+    };
+    (@resolve $idx:tt, $backend:ident, $resources:ident) => {
+        T::resolve($backend, $resources)
+    };
+}
+
+tuple_binding!(0 T0);
+tuple_binding!(0 T0, 1 T1);
+tuple_binding!(0 T0, 1 T1, 2 T2);
+tuple_binding!(0 T0, 1 T1, 2 T2, 3 T3);
+tuple_binding!(0 T0, 1 T1, 2 T2, 3 T3, 4 T4);
+tuple_binding!(0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5);
+tuple_binding!(0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6);
+tuple_binding!(0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7);
+
+array_binding!(
+    1 => (0),
+    2 => (0 1),
+    3 => (0 1 2),
+    4 => (0 1 2 3),
+    5 => (0 1 2 3 4),
+    6 => (0 1 2 3 4 5),
+    7 => (0 1 2 3 4 5 6),
+    8 => (0 1 2 3 4 5 6 7)
+);
+
 physical_resource_bind!(RenderTargetResource => CPU);
-physical_resource_bind!(DepthStencilResource => CPU);
+physical_resource_bind!(DepthStencilResource => DSV);
 physical_resource_bind!(ShaderResource => GPU);
-physical_resource_bind!(DepthReadResource => CPU);
-physical_resource_bind!(DepthWriteResource => CPU);
+physical_resource_bind!(DepthReadResource => DSV);
+physical_resource_bind!(DepthWriteResource => DSV);
+physical_resource_bind!(UnorderedAccessResource => GPU);
+physical_resource_bind!(BufferShaderResource => GPU);
+physical_resource_bind!(BufferUnorderedAccessResource => GPU);
+
+// a resource owned outside of the graph, see `FrameGraph::import_resource`
+pub struct ImportedResource(FrameGraphResource);
 
 typed_resource_transition!(RenderTargetResource => ShaderResource);
 typed_resource_transition!(DepthReadResource => ShaderResource);
 typed_resource_transition!(DepthWriteResource => ShaderResource);
 typed_resource_transition!(DepthWriteResource => DepthReadResource);
 typed_resource_transition!(DepthReadResource => DepthWriteResource);
+typed_resource_transition!(RenderTargetResource => UnorderedAccessResource);
+typed_resource_transition!(UnorderedAccessResource => UnorderedAccessResource);
+typed_resource_transition!(UnorderedAccessResource => ShaderResource);
+typed_resource_transition!(BufferUnorderedAccessResource => BufferShaderResource);
+typed_resource_transition!(BufferUnorderedAccessResource => BufferUnorderedAccessResource);
+typed_resource_transition!(ImportedResource => RenderTargetResource);
+typed_resource_transition!(ImportedResource => ShaderResource);
+typed_resource_transition!(ImportedResource => UnorderedAccessResource);
+typed_resource_transition!(ImportedResource => DepthWriteResource);
+typed_resource_transition!(ImportedResource => DepthReadResource);
+
+// spelled out through an alias, derivative can't parse `dyn`
+type PassExec<B> = Box<dyn FnMut(&B, <B as Backend>::CommandList) + Send>;
+
+// what `FrameGraph::add_pass` takes, runs with the physical resources the
+// pass bound
+pub type PassFn<B, T> = Box<dyn FnMut(<B as Backend>::CommandList, &<T as ResourceBinding>::PhysicalResource) + Send>;
 
 #[derive(Derivative)]
-#[derivative(Debug)]
-struct RenderPass {
-    resources: Vec<(u32, TransitionFlags)>,
+#[derivative(Debug(bound=""))]
+struct RenderPass<B: Backend> {
+    // prefixed with the sub-graphs it was added in, "Bloom/Down2"
+    name: String,
+    // path of the sub-graph it was added in, empty at the top level
+    subgraph: String,
+    queue: QueueType,
+    resources: Vec<(u32, TransitionFlags, SubresourceRange)>,
     #[derivative(Debug="ignore")]
     views: Vec<ResourceView>,
     #[derivative(Debug="ignore")]
-    exec: Box<FnMut(*mut ID3D12GraphicsCommandList, &())>,
-    param_size: usize,
-    params: Vec<(bool, u32, u32)>,
-    refcount: u32
+    // resolves the pass's bindings and calls the user's closure with them
+    exec: PassExec<B>,
+    // resources handed to the closure
+    bound: Vec<u32>,
+    // e.g. writes to memory the graph doesn't track, never culled
+    side_effects: bool,
+    // resources accessed through handles of an earlier frame
+    stale: Vec<String>,
+    // user events and sub-graphs the pass was added in, outermost first
+    events: Vec<String>,
+    // disabled passes keep their handles valid but never run their closure,
+    // see `FrameGraph::add_pass_if`
+    enabled: bool,
+    passthroughs: Vec<(u32, u32)>
 }
 
+// `from == to` only happens between two unordered access writes, where the
+// second one has to wait on the first
 #[derive(Debug, Copy, Clone)]
-struct ResourceTransition {
-    resource: u32,
-    from: TransitionFlags,
-    to: TransitionFlags
+pub struct ResourceTransition {
+    pub resource: u32,
+    // `ALL_SUBRESOURCES` once every subresource makes the same transition
+    pub subresource: u32,
+    pub from: ResourceStates,
+    pub to: ResourceStates,
+    // split barriers begin right after this pass (the last use in `from`),
+    // and end right before the pass the transition belongs to
+    pub begin: Option<usize>
 }
 
-enum ResourceBarrier {
-    Transition(*mut ID3D12Resource, D3D12_RESOURCE_STATES, D3D12_RESOURCE_STATES),
-    Alias(*mut ID3D12Resource, *mut ID3D12Resource)
+impl ResourceTransition {
+    fn to_barrier<R: Copy>(self, physical: &[Option<R>], flags: BarrierFlags) -> ResourceBarrier<R> {
+        let res = physical[self.resource as usize].expect("transition of a culled resource");
+
+        if self.from == self.to {
+            ResourceBarrier::Uav(res)
+        } else {
+            ResourceBarrier::Transition(res, self.subresource, self.from, self.to, flags)
+        }
+    }
 }
 
-impl Into<D3D12_RESOURCE_BARRIER> for ResourceBarrier {
-    fn into(self) -> D3D12_RESOURCE_BARRIER {
-        match self {
-            ResourceBarrier::Transition(resource, from, to) => {
-                unsafe {
-                    let mut barrier: D3D12_RESOURCE_BARRIER = ::std::mem::zeroed();
-
-                    barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_TRANSITION;
-                    barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
-
-                    (*barrier.u.Transition_mut()) = D3D12_RESOURCE_TRANSITION_BARRIER {
-                        pResource: resource,
-                        Subresource: 0,
-                        StateBefore: from,
-                        StateAfter: to,
-                    };
+// merges the per subresource transitions of a pass into one barrier for the
+// whole resource when all `counts[resource]` subresources make the same one,
+// unordered access barriers always cover the whole resource
+fn collapse_subresources(transitions: &mut Vec<ResourceTransition>, counts: &[u32]) {
+    let mut collapsed: Vec<ResourceTransition> = Vec::with_capacity(transitions.len());
+
+    for transition in transitions.iter() {
+        let same = |other: &ResourceTransition| {
+            other.resource == transition.resource &&
+            other.from == transition.from &&
+            other.to == transition.to &&
+            other.begin == transition.begin
+        };
 
-                    barrier
-                }
-            },
-            ResourceBarrier::Alias(before, after) => {
-                unsafe {
-                    let mut barrier: D3D12_RESOURCE_BARRIER = ::std::mem::zeroed();
+        if collapsed.iter().any(|other| same(other) && other.subresource == ALL_SUBRESOURCES) {
+            continue;
+        }
+
+        let matching = transitions.iter().filter(|other| same(other)).count() as u32;
+        if transition.from == transition.to || matching == counts[transition.resource as usize] {
+            collapsed.push(ResourceTransition {
+                subresource: ALL_SUBRESOURCES,
+                ..*transition
+            });
+        } else {
+            collapsed.push(*transition);
+        }
+    }
 
-                    barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_ALIASING;
-                    barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+    *transitions = collapsed;
+}
 
-                    (*barrier.u.Aliasing_mut()) = D3D12_RESOURCE_ALIASING_BARRIER {
-                        pResourceBefore: before,
-                        pResourceAfter: after
-                    };
+#[derive(Debug, Clone, PartialEq)]
+pub enum CullReason {
+    // disabled, and nothing reads what it falls back to
+    Disabled,
+    // writes nothing and has no side effects
+    NoOutputs,
+    // nothing kept reads any of these and none of them are graph outputs
+    UnusedOutputs(Vec<String>)
+}
 
-                    barrier
-                }
-            }
+#[derive(Debug, Clone)]
+pub struct CulledPass {
+    pub name: String,
+    // position in the order the passes were added
+    pub index: usize,
+    pub reason: CullReason
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompileReport {
+    pub culled: Vec<CulledPass>,
+    // reused a compiled graph of an earlier frame, nothing but the views of
+    // imported resources was rebuilt
+    pub cached: bool,
+    // bytes of transient resources alive at the same time in `add_pass`
+    // order and in the order the passes were scheduled in, before aliasing
+    pub declared_peak_memory: u64,
+    pub peak_memory: u64,
+    pub timings: CompileTimings
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64
+}
+
+// structures `compile` keeps around, as many as the heap allocator keeps
+// layouts for, so toggling passes back and forth doesn't recompile
+const COMPILED_CACHE_SIZE: usize = 8;
+
+// everything `compile` derives from the structure of a frame, reused as long as
+// a later frame hashes the same
+#[derive(Debug, Clone)]
+struct CompiledGraph {
+    hash: u64,
+    report: CompileReport,
+    // scheduled order of the passes that weren't culled
+    order: Vec<usize>,
+    transitions: Vec<Vec<ResourceTransition>>,
+    clears: Vec<Vec<u32>>,
+    final_transitions: Vec<Vec<ResourceStates>>,
+    final_barriers: Vec<ResourceTransition>,
+    queue_plan: QueuePlan,
+    // (lifetime, initial state, flags, culled) of every resource
+    resources: Vec<(TransientResourceLifetime, ResourceStates, ResourceFlags, bool)>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameGraphError {
+    // no passes were added, or all of them were culled
+    EmptyGraph,
+    // read by a pass before any pass wrote it
+    ReadBeforeWrite { pass: String, resource: String },
+    // handle from before the last `finish`
+    StaleHandle { pass: String, resource: String },
+    StaleOutput { resource: String },
+    // the same subresources are read and written (or written twice in
+    // different ways) by one pass
+    ConflictingStates { pass: String, resource: String },
+    DepthAsColorTarget { pass: String, resource: String },
+    // both halves of a history belong to one pass per frame
+    HistoryRequestedTwice { pass: String, history: String },
+    // the two sides of a passthrough differ in format or count
+    PassthroughMismatch { pass: String, from: String, to: String }
+}
+
+impl ::std::fmt::Display for FrameGraphError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            FrameGraphError::EmptyGraph => write!(f, "frame graph has no passes to execute"),
+            FrameGraphError::ReadBeforeWrite { ref pass, ref resource } => write!(f, "pass \"{}\" reads \"{}\" before anything wrote it", pass, resource),
+            FrameGraphError::StaleHandle { ref pass, ref resource } => write!(f, "pass \"{}\" uses \"{}\" through a handle of an earlier frame", pass, resource),
+            FrameGraphError::StaleOutput { ref resource } => write!(f, "output \"{}\" was marked through a handle of an earlier frame", resource),
+            FrameGraphError::ConflictingStates { ref pass, ref resource } => write!(f, "pass \"{}\" uses \"{}\" in conflicting states", pass, resource),
+            FrameGraphError::DepthAsColorTarget { ref pass, ref resource } => write!(f, "pass \"{}\" binds depth resource \"{}\" as a colour target", pass, resource),
+            FrameGraphError::HistoryRequestedTwice { ref pass, ref history } => write!(f, "pass \"{}\" requests history \"{}\" a second time this frame", pass, history),
+            FrameGraphError::PassthroughMismatch { ref pass, ref from, ref to } => write!(f, "pass \"{}\" passes \"{}\" through to \"{}\", which doesn't match it", pass, from, to)
+        }
+    }
+}
+
+impl ::std::error::Error for FrameGraphError {}
+
+enum PassClear<R> {
+    Discard(R),
+    RenderTarget(u32, [f32; 4]),
+    DepthStencil(u32, f32, u8)
+}
+
+// everything recorded for a pass besides its exec closure
+struct PassCommands<R> {
+    // fresh histories, cleared while they're still render targets
+    history_clears: Vec<PassClear<R>>,
+    barriers: Vec<ResourceBarrier<R>>,
+    clears: Vec<PassClear<R>>,
+    render_pass: Option<RenderPassOps>,
+    // first of the two timestamps around the pass
+    timestamp: Option<u32>,
+    // begin halves of split barriers, issued after the pass
+    begins: Vec<ResourceBarrier<R>>
+}
+
+fn record_clear<B: Backend>(backend: &B, list: B::CommandList, clear: &PassClear<B::Resource>) {
+    match *clear {
+        PassClear::Discard(resource) => backend.discard_resource(list, resource),
+        PassClear::RenderTarget(view_id, color) => backend.clear_render_target(list, view_id, color),
+        PassClear::DepthStencil(view_id, depth, stencil) => backend.clear_depth_stencil(list, view_id, depth, stencil)
+    }
+}
+
+// ends and begins the user events of `list` until `events` are open, so user
+// events enclose their passes even when the schedule interleaves them with
+// passes from outside
+fn switch_events<B: Backend>(backend: &B, list: B::CommandList, open: &mut Vec<String>, events: &[String]) {
+    let common = open.iter().zip(events).take_while(|&(a, b)| a == b).count();
+
+    for _ in common..open.len() {
+        backend.end_event(list);
+    }
+    open.truncate(common);
+
+    for event in &events[common..] {
+        backend.begin_event(list, event);
+        open.push(event.clone());
+    }
+}
+
+// returns the time spent recording, `open` are the user events open in `list`
+fn record_commands<B: Backend>(backend: &B, pass: &mut RenderPass<B>, list: B::CommandList, open: &mut Vec<String>, commands: &PassCommands<B::Resource>) -> Duration {
+    let now = Instant::now();
+
+    switch_events(backend, list, open, &pass.events);
+    backend.begin_event(list, &pass.name);
+
+    if let Some(timestamp) = commands.timestamp {
+        backend.write_timestamp(list, timestamp);
+    }
+
+    for clear in &commands.history_clears {
+        record_clear(backend, list, clear);
+    }
+
+    backend.resource_barrier(list, &commands.barriers);
+
+    for clear in &commands.clears {
+        record_clear(backend, list, clear);
+    }
+
+    if let Some(ref ops) = commands.render_pass {
+        let render_targets = ops.render_targets.iter().map(|&(_, attachment)| attachment).collect::<Vec<_>>();
+
+        // disabled passes still begin the render pass for its load ops
+        backend.begin_render_pass(list, &render_targets, ops.depth.map(|(_, attachment)| attachment));
+        if pass.enabled {
+            backend.execute_pass(list, &pass.name);
+            (pass.exec)(backend, list);
         }
+        backend.end_render_pass(list);
+    } else if pass.enabled {
+        backend.execute_pass(list, &pass.name);
+        (pass.exec)(backend, list);
+    }
+
+    if let Some(timestamp) = commands.timestamp {
+        backend.write_timestamp(list, timestamp + 1);
+        backend.resolve_timestamps(list, timestamp, 2);
+    }
+
+    if !commands.begins.is_empty() {
+        backend.resource_barrier(list, &commands.begins);
     }
+
+    backend.end_event(list);
+
+    now.elapsed()
+}
+
+#[derive(Derivative)]
+#[derivative(Debug(bound=""))]
+struct ExternalResource<B: Backend> {
+    resource_id: u32,
+    resource: B::Resource,
+    // state the resource is in when the frame starts
+    state: ResourceStates,
+    // state the resource has to be left in when the frame ends
+    final_state: ResourceStates
+}
+
+// attachments of a raster pass along with their load and store ops, see
+// `FrameGraph::set_render_passes`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderPassOps {
+    // (resource, attachment)
+    pub render_targets: Vec<(u32, RenderPassAttachment)>,
+    pub depth: Option<(u32, RenderPassAttachment)>
+}
+
+// a render target pair that outlives the frame, see
+// `FrameGraphBuilder::create_history`
+#[derive(Derivative)]
+#[derivative(Debug(bound=""))]
+struct HistoryResource<B: Backend> {
+    name: String,
+    desc: ResourceDesc,
+    clear_color: [f32; 4],
+    // both live in a heap of their own, never aliased with transients
+    heap: B::Heap,
+    // written this frame and read by the next one, swapped by `finish`
+    current: B::Resource,
+    previous: B::Resource,
+    // `previous` was never written (first frame, resized), it's cleared
+    // before it's read
+    fresh: bool,
+    // (virtual id of `previous`, view to clear it through) this frame
+    bound: Option<(u32, u32)>
+}
+
+// how a render target or depth stencil is initialized on its first use, so
+// whatever previously lived in the aliased memory never leaks into a pass
+#[derive(Debug, Copy, Clone)]
+struct ResourceClear {
+    view_id: u32,
+    value: ClearValue,
+    state: InitialResourceState
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -226,626 +638,3389 @@ pub struct TransientResourceLifetime {
     pub end: u32
 }
 
-#[derive(Derivative)]
-#[derivative(Debug)]
+#[derive(Debug)]
 pub struct TransientResource {
-    refcount: u32,
     resource_id: u32,
     usage: TransitionFlags,
+    // state of the first use, resources are placed in this state
+    initial_state: ResourceStates,
     pub lifetime: TransientResourceLifetime,
     pub size: u64,
     alignment: u64,
-    #[derivative(Debug="ignore")]
-    pub desc: D3D12_RESOURCE_DESC,
-    pub name: &'static str
+    pub desc: ResourceDesc,
+    pub name: String,
+    // that created it, `None` for imported resources and histories
+    pub pass: Option<String>,
+    pub imported: bool,
+    // read after the graph executed, the passes producing it are never culled
+    output: bool,
+    // only used by culled passes, so never placed
+    pub culled: bool,
+    clear: Option<ResourceClear>
+}
+
+impl TransientResource {
+    pub fn initial_state(&self) -> ResourceStates {
+        self.initial_state
+    }
+
+    pub fn clear_value(&self) -> Option<ClearValue> {
+        self.clear.map(|clear| clear.value)
+    }
+
+    // (mips, array slices), buffers only ever have the one subresource
+    pub fn subresources(&self) -> (u32, u32) {
+        match self.desc.dimension {
+            ResourceDimension::Buffer => (1, 1),
+            _ => (self.desc.mip_levels.max(1) as u32, self.desc.depth_or_array_size.max(1) as u32)
+        }
+    }
 }
 
 impl ::std::hash::Hash for TransientResource {
     fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
         self.usage.hash(state);
+        self.initial_state.hash(state);
         self.lifetime.start.hash(state);
         self.lifetime.end.hash(state);
         self.size.hash(state);
+        self.desc.hash(state);
+        self.imported.hash(state);
+        self.culled.hash(state);
+
+        // resources are placed with their clear value, so it's part of the layout
+        match self.clear_value() {
+            Some(ClearValue::Color(color)) => for channel in &color {
+                channel.to_bits().hash(state);
+            },
+            Some(ClearValue::DepthStencil(depth, stencil)) => {
+                depth.to_bits().hash(state);
+                stencil.hash(state);
+            },
+            None => {}
+        }
     }
 }
 
-pub struct FrameGraph {
-    device: *mut ID3D12Device,
+pub struct FrameGraph<B: Backend> {
+    backend: B,
 
     // TODO: renderpass contents should borrow from heap allocator (cache)
-    renderpasses: Vec<RenderPass>,
+    renderpasses: Vec<RenderPass<B>>,
+    // removed by the last `cull`, only kept around for `to_dot`
+    culled_passes: Vec<RenderPass<B>>,
     // transitions too? at least aliasing
     renderpass_transitions: Vec<Vec<ResourceTransition>>,
-    final_transitions: Vec<Option<TransitionFlags>>,
+    // resources cleared or discarded before each pass
+    renderpass_clears: Vec<Vec<u32>>,
+    // state of every subresource at the end of the frame
+    final_transitions: Vec<Vec<ResourceStates>>,
+    // transitions from the states the placed resources were left in back
+    // into the ones the frame starts from, by the pass first using them.
+    // worked out by every exec, the resources depend on the cached layout
+    carried_transitions: Vec<Vec<ResourceTransition>>,
+    // transitions back into the final state of imported resources, issued
+    // after the last pass
+    final_barriers: Vec<ResourceTransition>,
     resource_aliasing: Vec<bool>,
 
     resources: Vec<TransientResource>,
+    imports: Vec<ExternalResource<B>>,
+    // kept across frames, imported into every frame that asks for them
+    histories: Vec<HistoryResource<B>>,
+    // (view, color) of fresh histories, cleared by the first pass reading them
+    history_clears: Vec<Vec<(u32, [f32; 4])>>,
+    render_passes: bool,
+    // `None` for passes that don't rasterize
+    render_pass_ops: Vec<Option<RenderPassOps>>,
     views: Vec<ResourceView>,
-    heaps: HeapMemoryAllocator,
+    heaps: HeapMemoryAllocator<B>,
+
+    queue_plan: QueuePlan,
+    split_barriers: bool,
+    // fence values every queue reached by the end of the previous frame, the
+    // plan's values are relative to these
+    fence_values: [u64; 3],
+
+    // output resolution `TextureSize` is relative to
+    resolution: (u32, u32),
+
+    // user events and sub-graphs passes added from now on are recorded in,
+    // outermost first
+    events: Vec<String>,
+    // sub-graphs passes added from now on belong to, outermost first
+    scopes: Vec<String>,
+
+    // resources marked with `mark_output`, checked against `frame` on compile
+    outputs: Vec<FrameGraphResource>,
+    // misuse noticed while passes were added, reported by `compile`
+    errors: Vec<FrameGraphError>,
+    // bumped by `finish`
+    frame: u32,
+
+    schedule: ScheduleStrategy,
+    retained: bool,
+    profiler: Profiler,
+    // of the last `compile`, handed to the profiler along with the frame
+    compile_timings: CompileTimings,
+    // most recently used first
+    compiled: Vec<CompiledGraph>,
+    cache_stats: CacheStats,
 
     virtual_offset: u32,
     virtual_view: u32
 }
 
-impl FrameGraph {
-    pub fn new(device: *mut ID3D12Device) -> Self {
+impl<B: Backend> FrameGraph<B> {
+    pub fn new(backend: B) -> Self {
         FrameGraph {
-            device,
+            backend,
             renderpasses: Vec::new(),
+            culled_passes: Vec::new(),
             renderpass_transitions: Vec::new(),
+            renderpass_clears: Vec::new(),
             final_transitions: Vec::new(),
+            carried_transitions: Vec::new(),
+            final_barriers: Vec::new(),
             resource_aliasing: Vec::new(),
             resources: Vec::new(),
+            imports: Vec::new(),
+            histories: Vec::new(),
+            history_clears: Vec::new(),
+            render_passes: false,
+            render_pass_ops: Vec::new(),
             views: Vec::new(),
-            heaps: HeapMemoryAllocator::new(device),
+            heaps: HeapMemoryAllocator::new(),
+            queue_plan: QueuePlan::default(),
+            split_barriers: true,
+            fence_values: [0; 3],
+            resolution: (1280, 720),
+            events: Vec::new(),
+            scopes: Vec::new(),
+            outputs: Vec::new(),
+            errors: Vec::new(),
+            frame: 0,
+            schedule: ScheduleStrategy::Declaration,
+            retained: true,
+            profiler: Profiler::new(),
+            compile_timings: CompileTimings::default(),
+            compiled: Vec::new(),
+            cache_stats: CacheStats::default(),
             virtual_offset: 0,
             virtual_view: 0,
         }
     }
 
-    pub fn add_pass<T, Init>(&mut self, name: &'static str, init: Init, exec: Box<FnMut(*mut ID3D12GraphicsCommandList, &T::PhysicalResource)>) -> T
-        where T: ResourceBinding + Sized /*+ Copy + Clone */,
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    // falls back to issuing every transition right before the pass that needs it
+    pub fn set_split_barriers(&mut self, enabled: bool) {
+        self.split_barriers = enabled;
+    }
+
+    // order passes are executed in, `add_pass` order by default
+    pub fn set_schedule(&mut self, schedule: ScheduleStrategy) {
+        self.schedule = schedule;
+    }
+
+    // reuse the compiled graph while frames keep the same structure, on by
+    // default
+    pub fn set_retained(&mut self, enabled: bool) {
+        self.retained = enabled;
+
+        if !enabled {
+            self.compiled.clear();
+        }
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache_stats
+    }
+
+    // wraps every pass drawing to render targets or depth in a native render
+    // pass, with load and store ops derived from the graph instead of clears
+    pub fn set_render_passes(&mut self, enabled: bool) {
+        self.render_passes = enabled;
+    }
+
+    // by pass in execution order, `None` unless render passes are enabled and
+    // the pass rasterizes
+    pub fn render_pass_ops(&self, pass: usize) -> Option<&RenderPassOps> {
+        self.render_pass_ops.get(pass).and_then(|ops| ops.as_ref())
+    }
+
+    // times every pass on the GPU and CPU, results show up in `profiler` a few
+    // frames after they were recorded
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler.set_enabled(enabled);
+    }
+
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    // passes added until the matching `end_event` are recorded inside a debug
+    // event named `name`, events nest
+    pub fn begin_event<N: Into<String>>(&mut self, name: N) {
+        self.events.push(name.into());
+    }
+
+    pub fn end_event(&mut self) {
+        self.events.pop().expect("end_event without a matching begin_event");
+    }
+
+    // passes and resources `build` adds are named "<name>/<their name>", and
+    // are grouped under `name` in captures, the DOT output and the profiler.
+    // sub-graphs nest, so the same chain of passes can be added several times
+    // under different names
+    pub fn add_subgraph<N, R, F>(&mut self, name: N, build: F) -> R
+        where N: Into<String>,
+              F: FnOnce(&mut Self) -> R
+    {
+        let name = name.into();
+        self.scopes.push(name.clone());
+        self.events.push(name);
+
+        let result = build(self);
+
+        self.events.pop();
+        self.scopes.pop();
+
+        result
+    }
+
+    // `name` prefixed with the sub-graphs currently added to
+    fn scoped_name(&self, name: String) -> String {
+        if self.scopes.is_empty() {
+            return name;
+        }
+
+        format!("{}/{}", self.scopes.join("/"), name)
+    }
+
+    pub fn transitions(&self, pass: usize) -> &[ResourceTransition] {
+        &self.renderpass_transitions[pass]
+    }
+
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    // can change between frames (window resizes, dynamic resolution), every
+    // cached heap layout was sized for the old resolution so they're dropped
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        if self.resolution == (width, height) {
+            return;
+        }
+
+        self.resolution = (width, height);
+        self.heaps.invalidate();
+        self.compiled.clear();
+    }
+
+    // brings a resource the graph doesn't own (e.g. a swapchain back buffer)
+    // into this frame, it is expected in `state` and left in `final_state`
+    pub fn import_resource<N: Into<String>>(&mut self, name: N, resource: B::Resource, format: TextureFormat, state: ResourceStates, final_state: ResourceStates) -> ImportedResource {
+        let name = self.scoped_name(name.into());
+        let virtual_id = self.virtual_offset;
+        self.virtual_offset += 1;
+
+        self.resources.push(TransientResource {
+            resource_id: virtual_id,
+            usage: TransitionFlags::empty(),
+            initial_state: ResourceStates::COMMON,
+            lifetime: TransientResourceLifetime { start: 0, end: 0 },
+            size: 0,
+            alignment: 0,
+            desc: ResourceDesc {
+                dimension: ResourceDimension::Texture2D,
+                width: 0,
+                height: 0,
+                depth_or_array_size: 1,
+                mip_levels: 1,
+                format: format.into(),
+                flags: ResourceFlags::empty(),
+            },
+            name: name.clone(),
+            pass: None,
+            imported: true,
+            output: false,
+            culled: false,
+            clear: None
+        });
+
+        self.imports.push(ExternalResource {
+            resource_id: virtual_id,
+            resource,
+            state,
+            final_state
+        });
+
+        ImportedResource(FrameGraphResource {
+            name: name.into(),
+            format: format.into(),
+            buffer: None,
+            array_size: 1,
+            view_id: 0,
+            resource_id: virtual_id,
+            frame: self.frame
+        })
+    }
+
+    pub fn add_pass<N, T, Init>(&mut self, name: N, init: Init, exec: PassFn<B, T>) -> T
+        where N: Into<String>,
+              T: ResourceBinding + 'static,
+              Init: FnOnce(&mut FrameGraphBuilder) -> T,
+              B: 'static
+    {
+        self.add_pass_if(name, true, init, exec)
+    }
+
+    // a disabled pass is still declared, so the handles it returns stay
+    // valid, but it drops its reads and never runs `exec`. Readers of its
+    // outputs get whatever `FrameGraphBuilder::passthrough` maps them to, or
+    // else the contents the outputs were created with (the clear color for
+    // `InitialResourceState::Clear`)
+    pub fn add_pass_if<N, T, Init>(&mut self, name: N, enabled: bool, init: Init, mut exec: PassFn<B, T>) -> T
+        where N: Into<String>,
+              T: ResourceBinding + 'static,
               Init: FnOnce(&mut FrameGraphBuilder) -> T,
+              B: 'static
     {
-        let mut builder = FrameGraphBuilder::new(self.device, self.virtual_offset, self.virtual_view);
+        let name = self.scoped_name(name.into());
+        let scope = self.scopes.join("/");
+        let mut builder = FrameGraphBuilder::new(self.virtual_offset, self.virtual_view, self.resolution, self.frame, &scope);
 
         let output = init(&mut builder);
 
-        self.virtual_offset = builder.counter;
         self.virtual_view = builder.view_counter;
 
-        let device = self.device;
+        let backend = &self.backend;
 
         self.resources.extend(builder.created.into_iter().map(|resource| {
-            let (size, alignment) = unsafe {
-                let alloc_info = (*device).GetResourceAllocationInfo(0, 1, &resource.desc as *const _);
-
-                (alloc_info.SizeInBytes, alloc_info.Alignment)
-            };
+            let (size, alignment) = backend.resource_allocation_info(&resource.desc);
 
             TransientResource {
-                refcount: 0,
                 resource_id: resource.resource_id,
                 usage: resource.flags,
+                initial_state: ResourceStates::COMMON,
                 lifetime: TransientResourceLifetime { start: 0, end: 0 },
                 // TODO: temp
                 size: size + alignment ,
                 alignment: alignment as _,
                 desc: resource.desc,
-                name: resource.name
+                name: resource.name,
+                pass: Some(name.clone()),
+                imported: false,
+                output: false,
+                culled: false,
+                clear: resource.clear
             }
         }));
 
+        for history in builder.histories {
+            self.import_history(&name, history);
+        }
+
+        for (from, to) in builder.mismatched {
+            self.errors.push(FrameGraphError::PassthroughMismatch {
+                pass: name.clone(),
+                from,
+                to
+            });
+        }
+
+        // histories were pushed after the created resources, ids have to
+        // match indices again
+        let first = self.resources.len() - (builder.counter - self.virtual_offset) as usize;
+        self.resources[first..].sort_by_key(|resource| resource.resource_id);
+
+        self.virtual_offset = builder.counter;
+
+        for (resource_id, clear) in builder.clears {
+            self.resources[resource_id as usize].clear = Some(clear);
+        }
+
         self.views.extend(builder.views.clone());
 
-        let exec = unsafe { ::std::mem::transmute(exec) };
+        let bindings = output.get_virtual_resources();
+        let bound = bindings.iter().map(|resource| resource.resource_id).collect();
 
-        self.renderpasses.push((RenderPass {
+        self.renderpasses.push(RenderPass {
+            name,
+            subgraph: scope,
+            queue: builder.queue,
             resources: builder.resources,
             views: builder.views,
-            exec: exec,
-            params: output.get_virtual_resources().iter().zip(output.is_cpus().iter()).map(|(r, &b)| (b, r.view_id, r.resource_id)).collect(),
-            param_size: output.is_cpus().iter().fold(0usize, |sum, &b| if b { ::std::mem::size_of::<D3D12_CPU_DESCRIPTOR_HANDLE>() } else { ::std::mem::size_of::<D3D12_GPU_DESCRIPTOR_HANDLE>() }),
-            refcount: 0
-        }));
+            exec: Box::new(move |backend: &B, list| {
+                let physical = T::resolve(backend, &mut bindings.iter());
+
+                exec(list, &physical)
+            }),
+            bound,
+            side_effects: builder.side_effects,
+            stale: builder.stale,
+            events: self.events.clone(),
+            enabled,
+            passthroughs: builder.passthroughs
+        });
 
         output
     }
 
-    pub fn cull(&mut self) {
-        // TODO: move to builder-phase?
-        for pass in &mut self.renderpasses {
-            for resource in &pass.resources {
-                if resource.1.has_read() {
-                    self.resources[resource.0 as usize].refcount += 1;
-                } else if resource.1.has_write() {
-                    pass.refcount += 1;
-                }
-            }
-        }
+    // (re)creates the history if it's new or its description changed (e.g. on
+    // a resize) and imports both halves into this frame
+    fn import_history(&mut self, pass: &str, request: HistoryRequest) {
+        let position = self.histories.iter().position(|history| history.name == request.name);
+
+        let idx = match position {
+            Some(idx) if self.histories[idx].desc == request.desc => idx,
+            _ => {
+                let backend = &mut self.backend;
+                let (size, alignment) = backend.resource_allocation_info(&request.desc);
+                let size = size.div_ceil(alignment) * alignment;
+
+                let heap = backend.create_heap(size * 2, request.desc.heap_flags());
+                let clear = Some(ClearValue::Color(request.clear_color));
+                let history = HistoryResource {
+                    name: request.name.clone(),
+                    desc: request.desc,
+                    clear_color: request.clear_color,
+                    heap,
+                    current: backend.create_placed_resource(heap, 0, &request.desc, ResourceStates::RENDER_TARGET, clear, &request.name),
+                    previous: backend.create_placed_resource(heap, size, &request.desc, ResourceStates::RENDER_TARGET, clear, &request.name),
+                    fresh: true,
+                    bound: None
+                };
 
-        use ::std::collections::vec_deque::VecDeque;
+                match position {
+                    Some(idx) => {
+                        let old = ::std::mem::replace(&mut self.histories[idx], history);
 
-        // push all resources that are never read to stack
-        let mut unused = VecDeque::new();
-        for (idx, resource) in self.resources.iter().enumerate() {
-            if resource.refcount == 0 {
-                unused.push_back(idx);
-            }
-        }
-
-        let mut prune_list = Vec::new();
-
-        loop {
-            // while the stack is not empty,
-            if let Some(resource) = unused.pop_front() {
-
-                // decrease the producers rc (if a pass's outputs are never
-                // read and has no side-effects then it can be culled)
-                let mut producer = &mut self.renderpasses[resource];
-                producer.refcount -= 1;
-               
-                // to cull, we push all of that pass's reads onto the unused
-                // stack (they are now suspects!)
-                //
-                // repeat until stack is empty
-                if producer.refcount == 0 {
-                    prune_list.push(self.resources[resource].lifetime.start);
-
-                    for resource in &producer.resources {
-                        if resource.1.has_read() {
-                            self.resources[resource.0 as usize].refcount -= 1;
-                            if self.resources[resource.0 as usize].refcount == 0 {
-                                unused.push_back(resource.0 as usize);
-                            }
-                        }
+                        // frames in flight may still read the old halves, the
+                        // backend releases them once they finished
+                        backend.release_resource(old.current);
+                        backend.release_resource(old.previous);
+                        backend.release_heap(old.heap);
+
+                        idx
+                    },
+                    None => {
+                        self.histories.push(history);
+
+                        self.histories.len() - 1
                     }
-                } 
-            } else {
-                break;
+                }
             }
-        }
+        };
 
-        // TODO: sort prune list so we delete in right order
-        // remove our culled passes
-        for i in prune_list {
-            self.renderpasses.remove(i as usize);
+        // the second request still gets its resources so ids keep matching
+        // indices, `compile` fails anyway
+        let history = &mut self.histories[idx];
+        if history.bound.is_some() {
+            self.errors.push(FrameGraphError::HistoryRequestedTwice {
+                pass: pass.to_string(),
+                history: history.name.clone()
+            });
+        } else {
+            history.bound = Some((request.previous, request.clear_view));
         }
-    }
 
-    fn find_lifetimes(&mut self) {
-        // find first and last usage of a resource
-        for (idx, mut resource) in self.resources.iter_mut().enumerate() {
-            let first_use = self.renderpasses.iter().position(|pass| pass.resources.iter().find(|res| res.0 == idx as u32).is_some());
-            let last_use = self.renderpasses.iter().rposition(|pass| pass.resources.iter().find(|res| res.0 == idx as u32).is_some());
+        // a fresh pair is still in the state it was created in, afterwards
+        // both are left readable at the end of every frame
+        let read = TransitionFlags::SHADER_RESOURCE.into_resource_state();
+        let state = if history.fresh { ResourceStates::RENDER_TARGET } else { read };
 
-            resource.lifetime = TransientResourceLifetime {
-                start: first_use.unwrap() as u32,
-                end: last_use.unwrap() as u32
-            };
+        for &(resource_id, resource, clear) in &[(request.current, history.current, Some(request.clear)), (request.previous, history.previous, None)] {
+            self.resources.push(TransientResource {
+                resource_id,
+                usage: TransitionFlags::empty(),
+                initial_state: ResourceStates::COMMON,
+                lifetime: TransientResourceLifetime { start: 0, end: 0 },
+                size: 0,
+                alignment: 0,
+                desc: request.desc,
+                name: request.name.clone(),
+                pass: None,
+                imported: true,
+                output: false,
+                culled: false,
+                clear
+            });
+
+            self.imports.push(ExternalResource {
+                resource_id,
+                resource,
+                state,
+                final_state: read
+            });
         }
     }
 
-    fn generate_barriers(&mut self) {
-        // generate all transition barriers
-        //
-        // TODO: cache all Vec allocations
-        //
-        let mut current_states = vec![TransitionFlags::empty(); self.resources.len()];
-        let mut prev_states = vec![TransitionFlags::empty(); self.resources.len()];
-        let mut cache_passes = vec![None; self.resources.len()];
-        let mut prev_passes = vec![0usize; self.resources.len()];
-        let mut aggregate_state = vec![TransitionFlags::empty(); self.resources.len()];
+    // loads clear or discard a target on its first use this frame, anything
+    // else keeps what's there, stores keep it if a later pass or the next
+    // owner (imports, outputs) looks at it
+    fn find_render_pass_ops(&mut self) {
+        self.render_pass_ops.clear();
 
-        self.renderpass_transitions.resize(self.renderpasses.len(), Vec::new());
-        self.final_transitions.resize(self.resources.len(), None);
-        self.resource_aliasing.clear();
-        self.resource_aliasing.resize(self.resources.len(), false);
+        if !self.render_passes {
+            return;
+        }
 
-        for (i, pass) in self.renderpasses.iter().enumerate() {
-            for resource in &pass.resources {
-                let idx = resource.0 as usize;
+        let touches = |pass: &RenderPass<B>, resource: u32| pass.resources.iter().any(|res| res.0 == resource);
 
-                let transition = resource.1;
-                let prev_transition = prev_states[idx];
+        for (idx, pass) in self.renderpasses.iter().enumerate() {
+            let mut ops = RenderPassOps::default();
 
-                // TODO: disjoint barriers?
-                if let Some(last) = self.final_transitions[idx] {
-                    self.renderpass_transitions[i].push(ResourceTransition {
-                        resource: resource.0,
-                        from: last,
-                        to: transition
-                    });
+            for view in &pass.views {
+                let (format, depth) = match view.desc {
+                    ResourceViewDesc::RenderTarget { format, .. } => (format, false),
+                    ResourceViewDesc::DepthStencil { format, .. } => (format, true),
+                    _ => continue
+                };
 
-                    self.final_transitions[idx] = None;
+                // e.g. the view a fresh history is cleared through
+                let attached = pass.resources.iter().any(|res| res.0 == view.resource_id && res.1.intersects(TransitionFlags::RENDER_TARGET | TransitionFlags::DEPTH_WRITE | TransitionFlags::DEPTH_READ));
+                if !attached {
+                    continue;
                 }
 
-                aggregate_state[idx].insert(transition);
-
-                if transition.has_write() {
-                    if current_states[idx].has_read() {
-                        if let Some(pass_idx) = cache_passes[idx] {
-                            self.renderpass_transitions[pass_idx as usize].push(ResourceTransition {
-                                resource: resource.0,
-                                from: prev_states[idx],
-                                to: current_states[idx]
-                            });
-
-                            prev_states[idx] = current_states[idx];
-                            cache_passes[idx] = None;
-                        }
-                    }
+                let resource = &self.resources[view.resource_id as usize];
+                let earlier = self.renderpasses[..idx].iter().any(|other| touches(other, view.resource_id));
+                let later = self.renderpasses[idx + 1..].iter().any(|other| touches(other, view.resource_id));
+
+                let load = match resource.clear {
+                    Some(clear) if !earlier => match clear.state {
+                        InitialResourceState::Clear => LoadOp::Clear(clear.value),
+                        InitialResourceState::DontCare => LoadOp::Discard
+                    },
+                    _ if earlier || resource.imported => LoadOp::Preserve,
+                    _ => LoadOp::Discard
+                };
 
-                    if let Some(pass_idx) = cache_passes[idx] {
-                        self.renderpass_transitions[pass_idx as usize].push(ResourceTransition {
-                            resource: resource.0,
-                            from: prev_states[idx],
-                            to: current_states[idx]
-                        });
+                let store = if later || resource.imported || resource.output { StoreOp::Preserve } else { StoreOp::Discard };
 
-                        cache_passes[idx] = Some(prev_passes[idx]);
-                        prev_states[idx] = current_states[idx];
-                        current_states[idx] = transition;
-                    } else {
-                        if prev_transition.is_empty() {
-                            prev_states[idx] = transition;
-                        }
+                let attachment = (view.resource_id, RenderPassAttachment {
+                    view_id: view.view_id,
+                    format,
+                    load,
+                    store
+                });
 
-                        current_states[idx] = transition;
-                        cache_passes[idx] = Some(i);
-                    }
+                if depth {
+                    ops.depth = Some(attachment);
                 } else {
-                    if current_states[idx].has_write() {
-                        prev_states[idx] = current_states[idx];
-                        cache_passes[idx] = Some(i);
-                        current_states[idx] = transition;
-                    }
-
-                    current_states[idx].insert(transition);
+                    ops.render_targets.push(attachment);
                 }
-
-                prev_passes[idx] = i;
             }
-        }
-
-        //println!("{:#?}", self.final_transitions);
 
+            let rasterizes = !ops.render_targets.is_empty() || ops.depth.is_some();
+            self.render_pass_ops.push(if rasterizes { Some(ops) } else { None });
+        }
+    }
 
-        for i in 0..self.resources.len() {
-            let prev_state = prev_states[i];
-            let current_state = current_states[i];
+    // runs after the passes were culled and ordered
+    fn find_history_clears(&mut self) {
+        self.history_clears.clear();
+        self.history_clears.resize(self.renderpasses.len(), Vec::new());
 
-            self.final_transitions[i] = Some(current_state);
-            if prev_state != current_state {
-                if let Some(pass_idx) = cache_passes[i] {
-                    self.renderpass_transitions[pass_idx].push(ResourceTransition {
-                        resource: i as u32,
-                        from: prev_state,
-                        to: current_state
-                    });
+        for history in self.histories.iter().filter(|history| history.fresh) {
+            if let Some((previous, view_id)) = history.bound {
+                let first_use = self.renderpasses.iter().position(|pass| pass.resources.iter().any(|res| res.0 == previous));
 
+                if let Some(pass) = first_use {
+                    self.history_clears[pass].push((view_id, history.clear_color));
                 }
             }
         }
+    }
 
-        //println!("{:#?}", self.renderpass_transitions);
+    // marks a resource as read after the graph executed (e.g. by a readback
+    // or the next frame), so the passes producing it aren't culled
+    pub fn mark_output<T: ResourceBinding>(&mut self, resource: &T) {
+        self.outputs.extend(resource.get_virtual_resources());
+    }
+
+    // strips the reads of disabled passes and points readers of their
+    // passthroughs at the resources passed through, afterwards disabled
+    // passes only differ from others in not running their closure
+    fn disable_passes(&mut self) {
+        // resource id every resource is read through
+        let mut remap = (0..self.resources.len() as u32).collect::<Vec<_>>();
+
+        for pass in &mut self.renderpasses {
+            for resource in &mut pass.resources {
+                resource.0 = remap[resource.0 as usize];
+            }
+            for bound in &mut pass.bound {
+                *bound = remap[*bound as usize];
+            }
+
+            if pass.enabled {
+                continue;
+            }
+
+            pass.resources.retain(|res| res.1.has_write());
+            pass.side_effects = false;
+
+            let resources = &pass.resources;
+            pass.bound.retain(|&bound| resources.iter().any(|res| res.0 == bound));
+
+            // chains of disabled passes pass through all the way
+            for &(from, to) in &pass.passthroughs {
+                remap[to as usize] = remap[from as usize];
+            }
+        }
+
+        for (to, &from) in remap.iter().enumerate() {
+            if from as usize != to && self.resources[to].output {
+                self.resources[from as usize].output = true;
+            }
+        }
+
+        let pass_views = self.renderpasses.iter_mut().flat_map(|pass| pass.views.iter_mut());
+        for view in self.views.iter_mut().chain(pass_views) {
+            view.resource_id = remap[view.resource_id as usize];
+        }
+    }
+
+    fn validate(&mut self) -> Result<(), FrameGraphError> {
+        if self.renderpasses.is_empty() {
+            return Err(FrameGraphError::EmptyGraph);
+        }
+
+        if let Some(error) = self.errors.first() {
+            return Err(error.clone());
+        }
+
+        for output in &self.outputs {
+            if output.frame != self.frame {
+                return Err(FrameGraphError::StaleOutput { resource: output.name.to_string() });
+            }
+
+            self.resources[output.resource_id as usize].output = true;
+        }
+
+        // imported resources come in with whatever the previous owner wrote
+        let mut written = self.resources.iter().map(|resource| resource.imported).collect::<Vec<_>>();
+
+        for pass in &self.renderpasses {
+            if let Some(resource) = pass.stale.first() {
+                return Err(FrameGraphError::StaleHandle { pass: pass.name.clone(), resource: resource.clone() });
+            }
+
+            for (idx, &(res, access, range)) in pass.resources.iter().enumerate() {
+                let resource = &self.resources[res as usize];
+                if resource.desc.format.is_depth() && access.intersects(TransitionFlags::RENDER_TARGET | TransitionFlags::UNORDERED_ACCESS) {
+                    return Err(FrameGraphError::DepthAsColorTarget { pass: pass.name.clone(), resource: resource.name.clone() });
+                }
+
+                if !access.has_write() && !written[res as usize] {
+                    return Err(FrameGraphError::ReadBeforeWrite { pass: pass.name.clone(), resource: resource.name.clone() });
+                }
+
+                // reads of one mip alongside writes of another are fine
+                let (mips, slices) = resource.subresources();
+                let subresources = range.indices(mips, slices);
+                for &(other, other_access, other_range) in &pass.resources[idx + 1..] {
+                    if other == res && other_access != access && (access.has_write() || other_access.has_write()) &&
+                        other_range.indices(mips, slices).iter().any(|sub| subresources.contains(sub)) {
+                        return Err(FrameGraphError::ConflictingStates { pass: pass.name.clone(), resource: resource.name.clone() });
+                    }
+                }
+            }
+
+            for &(res, access, _) in &pass.resources {
+                if access.has_write() {
+                    written[res as usize] = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // removes every pass that doesn't contribute to a graph output, an
+    // imported resource or a pass with side effects
+    //
+    // walks the passes backwards keeping track of which resources are still
+    // needed, writes may only cover part of a resource (a mip, blending), so a
+    // kept pass needs every earlier producer of everything it touches
+    pub fn cull(&mut self) -> Vec<CulledPass> {
+        let mut needed = self.resources.iter().map(|resource| resource.imported || resource.output).collect::<Vec<_>>();
+        let mut keep = vec![false; self.renderpasses.len()];
+        let mut culled = Vec::new();
+
+        for (idx, pass) in self.renderpasses.iter().enumerate().rev() {
+            let writes = pass.resources.iter()
+                .filter(|res| res.1.has_write())
+                .map(|res| res.0 as usize)
+                .collect::<Vec<_>>();
+
+            if (pass.side_effects && pass.enabled) || writes.iter().any(|&res| needed[res]) {
+                keep[idx] = true;
+
+                for res in &pass.resources {
+                    needed[res.0 as usize] = true;
+                }
+            } else {
+                let reason = if !pass.enabled {
+                    CullReason::Disabled
+                } else if writes.is_empty() {
+                    CullReason::NoOutputs
+                } else {
+                    CullReason::UnusedOutputs(writes.iter().map(|&res| self.resources[res].name.clone()).collect())
+                };
+
+                culled.push(CulledPass {
+                    name: pass.name.clone(),
+                    index: idx,
+                    reason
+                });
+            }
+        }
+
+        culled.reverse();
+        self.remove_passes(&culled);
+
+        for (idx, resource) in self.resources.iter_mut().enumerate() {
+            resource.culled = !resource.imported && !self.renderpasses.iter().any(|pass| pass.resources.iter().any(|res| res.0 == idx as u32));
+        }
+
+        culled
+    }
+
+    // reorders the passes left after culling, returns the new order along
+    // with the peak memory before and after
+    fn schedule_passes(&mut self) -> (Vec<usize>, u64, u64) {
+        let passes = self.renderpasses.iter().map(|pass| OrderPass {
+            accesses: pass.resources.iter().map(|&(res, access, _)| (res, access.has_write(), access.into_queue_state(pass.queue))).collect(),
+            side_effects: pass.side_effects
+        }).collect::<Vec<_>>();
+
+        let sizes = self.resources.iter().map(|resource| if resource.imported || resource.culled { 0 } else { resource.size }).collect::<Vec<_>>();
+
+        let declared = (0..passes.len()).collect::<Vec<_>>();
+        let order = order_passes(&passes, &sizes, self.schedule);
+
+        let declared_peak = peak_memory(&passes, &declared, &sizes);
+        let peak = peak_memory(&passes, &order, &sizes);
+
+        self.apply_order(&order);
+
+        (order, declared_peak, peak)
+    }
+
+    fn apply_order(&mut self, order: &[usize]) {
+        let mut passes = ::std::mem::take(&mut self.renderpasses).into_iter().map(Some).collect::<Vec<_>>();
+
+        self.renderpasses = order.iter().map(|&idx| passes[idx].take().unwrap()).collect();
+    }
+
+    fn remove_passes(&mut self, culled: &[CulledPass]) {
+        let passes = ::std::mem::take(&mut self.renderpasses);
+        self.culled_passes.clear();
+
+        for (idx, pass) in passes.into_iter().enumerate() {
+            if culled.iter().any(|culled| culled.index == idx) {
+                self.culled_passes.push(pass);
+            } else {
+                self.renderpasses.push(pass);
+            }
+        }
+    }
+
+    // covers everything the compiled graph depends on, the physical imported
+    // resources aside
+    fn structural_hash(&self) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+
+        for pass in &self.renderpasses {
+            pass.name.hash(&mut hasher);
+            pass.queue.hash(&mut hasher);
+            pass.side_effects.hash(&mut hasher);
+            pass.enabled.hash(&mut hasher);
+            pass.resources.hash(&mut hasher);
+        }
+
+        for resource in &self.resources {
+            resource.name.hash(&mut hasher);
+            resource.usage.hash(&mut hasher);
+            resource.desc.hash(&mut hasher);
+            resource.imported.hash(&mut hasher);
+            resource.output.hash(&mut hasher);
+
+            match resource.clear_value() {
+                Some(ClearValue::Color(color)) => for channel in &color {
+                    channel.to_bits().hash(&mut hasher);
+                },
+                Some(ClearValue::DepthStencil(depth, stencil)) => {
+                    depth.to_bits().hash(&mut hasher);
+                    stencil.hash(&mut hasher);
+                },
+                None => {}
+            }
+        }
+
+        for import in &self.imports {
+            import.resource_id.hash(&mut hasher);
+            import.state.hash(&mut hasher);
+            import.final_state.hash(&mut hasher);
+        }
+
+        for view in &self.views {
+            view.resource_id.hash(&mut hasher);
+            view.view_id.hash(&mut hasher);
+            view.desc.hash(&mut hasher);
+        }
+
+        self.split_barriers.hash(&mut hasher);
+        self.schedule.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    // puts back what `compile` derived the last time the graph looked like this
+    fn restore(&mut self, compiled: &CompiledGraph) {
+        self.remove_passes(&compiled.report.culled);
+        self.apply_order(&compiled.order);
+
+        for (resource, &(lifetime, state, flags, culled)) in self.resources.iter_mut().zip(&compiled.resources) {
+            resource.lifetime = lifetime;
+            resource.initial_state = state;
+            resource.desc.flags = flags;
+            resource.culled = culled;
+        }
+
+        self.renderpass_transitions = compiled.transitions.clone();
+        self.renderpass_clears = compiled.clears.clone();
+        self.final_transitions = compiled.final_transitions.clone();
+        self.final_barriers = compiled.final_barriers.clone();
+        self.queue_plan = compiled.queue_plan.clone();
+
+        self.resource_aliasing.clear();
+        self.resource_aliasing.resize(self.resources.len(), false);
+        for import in &self.imports {
+            self.resource_aliasing[import.resource_id as usize] = true;
+        }
+    }
+
+    fn find_lifetimes(&mut self) {
+        // find first and last usage of a resource
+        for (idx, resource) in self.resources.iter_mut().enumerate() {
+            let first_use = self.renderpasses.iter().position(|pass| pass.resources.iter().find(|res| res.0 == idx as u32).is_some());
+            let last_use = self.renderpasses.iter().rposition(|pass| pass.resources.iter().find(|res| res.0 == idx as u32).is_some());
+
+            // culled resources (and unused imports) don't live at all
+            resource.lifetime = match (first_use, last_use) {
+                (Some(first_use), Some(last_use)) => TransientResourceLifetime {
+                    start: first_use as u32,
+                    end: last_use as u32
+                },
+                _ => TransientResourceLifetime { start: 0, end: 0 }
+            };
+        }
+
+        // passes on other queues may run alongside any of the uses, the
+        // memory can't be handed to anything else until they're synced up
+        for (idx, resource) in self.resources.iter_mut().enumerate() {
+            for (pass_idx, pass) in self.renderpasses.iter().enumerate() {
+                if pass.resources.iter().any(|res| res.0 == idx as u32) {
+                    let (start, end) = self.queue_plan.concurrent_range(pass_idx);
+
+                    resource.lifetime.start = resource.lifetime.start.min(start as u32);
+                    resource.lifetime.end = resource.lifetime.end.max(end as u32);
+                }
+            }
+        }
+    }
+
+    fn first_use(&self, resource: usize) -> Option<usize> {
+        self.renderpasses.iter().position(|pass| pass.resources.iter().any(|res| res.0 == resource as u32))
+    }
+
+    fn plan_queues(&mut self) {
+        // the frame starts with a transition out of the state the last one
+        // left each resource in, at the pass first using it
+        let mut carried = vec![false; self.renderpasses.len()];
+        for (idx, resource) in self.resources.iter().enumerate() {
+            if let Some(first_use) = self.first_use(idx).filter(|_| !resource.imported) {
+                let queue = self.renderpasses[first_use].queue;
+
+                if self.final_transitions[idx].iter().any(|&state| !queue.supports(state | resource.initial_state)) {
+                    carried[first_use] = true;
+                }
+            }
+        }
+
+        let passes = self.renderpasses.iter().zip(self.renderpass_transitions.iter()).enumerate().map(|(idx, (pass, transitions))| QueuePass {
+            queue: pass.queue,
+            resources: pass.resources.iter().map(|res| res.0).collect(),
+            graphics_transitions: carried[idx] || transitions.iter().any(|transition| !pass.queue.supports(transition.from | transition.to))
+        }).collect::<Vec<_>>();
+
+        self.queue_plan = plan_queues(&passes);
+    }
+
+    // both halves of a split barrier have to be recorded into the same
+    // command list, and transitions moved onto the graphics queue go whole
+    fn resolve_split_barriers(&mut self) {
+        let plan = &self.queue_plan;
+
+        for (idx, (pass, transitions)) in self.renderpasses.iter().zip(self.renderpass_transitions.iter_mut()).enumerate() {
+            for transition in transitions.iter_mut() {
+                if let Some(begin) = transition.begin {
+                    let hoisted = plan.transition_batch[idx].is_some() && !pass.queue.supports(transition.from | transition.to);
+
+                    if hoisted || plan.pass_batch[begin] != plan.pass_batch[idx] {
+                        transition.begin = None;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn queue_plan(&self) -> &QueuePlan {
+        &self.queue_plan
+    }
+
+    // graphviz description of the compiled graph, passes are boxes and
+    // resources ellipses, edges carry the access and the barriers issued for
+    // it right before the pass. Sub-graphs are drawn as nested clusters
+    // holding their passes and the resources named in them
+    pub fn to_dot(&self) -> String {
+        use std::fmt::Write;
+
+        fn escape(name: &str) -> String {
+            name.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+
+        // ("Bloom/Down2", "Blur") for "Bloom/Down2/Blur"
+        fn split_scope(name: &str) -> (&str, &str) {
+            match name.rfind('/') {
+                Some(idx) => (&name[..idx], &name[idx + 1..]),
+                None => ("", name)
+            }
+        }
+
+        // the nodes of `scope` followed by a cluster for each sub-graph
+        // directly below it
+        fn write_cluster(dot: &mut String, nodes: &[(&str, String)], scope: &str, depth: usize, clusters: &mut usize) {
+            let indent = "    ".repeat(depth);
+
+            for &(path, ref node) in nodes.iter().filter(|&&(path, _)| path == scope) {
+                writeln!(dot, "{}{}", indent, node).unwrap();
+            }
+
+            let mut children: Vec<&str> = Vec::new();
+            for &(path, _) in nodes {
+                let rest = if scope.is_empty() {
+                    path
+                } else if path.starts_with(scope) && path[scope.len()..].starts_with('/') {
+                    &path[scope.len() + 1..]
+                } else {
+                    continue;
+                };
+
+                if rest.is_empty() {
+                    continue;
+                }
+
+                let len = path.len() - rest.len() + rest.find('/').unwrap_or(rest.len());
+                let child = &path[..len];
+                if !children.contains(&child) {
+                    children.push(child);
+                }
+            }
+
+            for child in children {
+                writeln!(dot, "{}subgraph cluster{} {{", indent, clusters).unwrap();
+                writeln!(dot, "{}    label=\"{}\";", indent, escape(split_scope(child).1)).unwrap();
+                writeln!(dot, "{}    style=rounded;", indent).unwrap();
+                *clusters += 1;
+
+                write_cluster(dot, nodes, child, depth + 1, clusters);
+
+                writeln!(dot, "{}}}", indent).unwrap();
+            }
+        }
+
+        let mut dot = String::new();
+        // (sub-graph, declaration), edges only once every node is declared
+        let mut nodes: Vec<(&str, String)> = Vec::new();
+        let mut edges = Vec::new();
+
+        writeln!(dot, "digraph FrameGraph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [fontname=\"Helvetica\", fontsize=10];").unwrap();
+        writeln!(dot, "    edge [fontname=\"Helvetica\", fontsize=8];").unwrap();
+
+        for (idx, resource) in self.resources.iter().enumerate() {
+            let style = match (resource.imported, resource.output, resource.culled) {
+                (_, _, true) => "style=dashed, color=grey, fontcolor=grey",
+                (true, _, _) => "peripheries=2",
+                (_, true, _) => "style=bold",
+                _ => "style=solid"
+            };
+
+            let (scope, name) = split_scope(&resource.name);
+            nodes.push((scope, format!("res{} [label=\"{}\", shape=ellipse, {}];", idx, escape(name), style)));
+        }
+
+        let passes = self.renderpasses.iter().enumerate().map(|(idx, pass)| (format!("pass{}", idx), pass, Some(idx)))
+            .chain(self.culled_passes.iter().enumerate().map(|(idx, pass)| (format!("culled{}", idx), pass, None)));
+
+        for (node, pass, compiled) in passes {
+            let name = if pass.subgraph.is_empty() { &pass.name[..] } else { &pass.name[pass.subgraph.len() + 1..] };
+            let mut label = match pass.queue {
+                QueueType::Graphics => escape(name),
+                queue => format!("{}\\n({:?})", escape(name), queue)
+            };
+            if !pass.enabled {
+                label.push_str("\\n(disabled)");
+            }
+            let style = if compiled.is_some() { "style=filled, fillcolor=lightblue" } else { "style=filled, fillcolor=lightgrey, color=grey, fontcolor=grey" };
+
+            nodes.push((&pass.subgraph, format!("{} [label=\"{}\", shape=box, {}];", node, label, style)));
+
+            for &(resource, access, range) in &pass.resources {
+                let mut label = format!("{:?}", access);
+                if range != SubresourceRange::all() {
+                    write!(label, " {:?}", range).unwrap();
+                }
+
+                let transitions = compiled.map_or(&[][..], |idx| &self.renderpass_transitions[idx][..]);
+                for transition in transitions.iter().filter(|transition| transition.resource == resource) {
+                    write!(label, "\\n{:?} -> {:?}", transition.from, transition.to).unwrap();
+                    if transition.subresource != ALL_SUBRESOURCES {
+                        write!(label, " [{}]", transition.subresource).unwrap();
+                    }
+                    if let Some(begin) = transition.begin {
+                        write!(label, " (split after {})", escape(&self.renderpasses[begin].name)).unwrap();
+                    }
+                }
+
+                let style = if compiled.is_some() { "" } else { ", style=dashed, color=grey, fontcolor=grey" };
+
+                if access.has_write() {
+                    edges.push(format!("{} -> res{} [label=\"{}\"{}];", node, resource, label, style));
+                } else {
+                    edges.push(format!("res{} -> {} [label=\"{}\"{}];", resource, node, label, style));
+                }
+            }
+        }
+
+        write_cluster(&mut dot, &nodes, "", 1, &mut 0);
+
+        for edge in edges {
+            writeln!(dot, "    {}", edge).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+
+        dot
+    }
+
+    pub fn write_dot<P: AsRef<::std::path::Path>>(&self, path: P) -> ::std::io::Result<()> {
+        ::std::fs::write(path, self.to_dot())
+    }
+
+    fn generate_barriers(&mut self) {
+        // generate all transition barriers
+        //
+        // states are tracked per subresource (mip and array slice), consecutive
+        // reads of a subresource are merged into one combined read state, so
+        // the transition into it is only placed (at the first reader) once the
+        // next write or the end of the frame closes the reads
+        //
+        // TODO: cache all Vec allocations
+        //
+        // first subresource of every resource in the per subresource arrays
+        let mut offsets = Vec::with_capacity(self.resources.len() + 1);
+        offsets.push(0);
+        for resource in &self.resources {
+            let (mips, slices) = resource.subresources();
+            let next = offsets[offsets.len() - 1] + (mips * slices) as usize;
+            offsets.push(next);
+        }
+        let subresource_count = offsets[self.resources.len()];
+
+        // `None` until the subresource has been placed
+        let mut current_states: Vec<Option<ResourceStates>> = vec![None; subresource_count];
+        let mut initial_states: Vec<Option<ResourceStates>> = vec![None; self.resources.len()];
+        // (first reader, combined reads, last use before the first reader)
+        let mut pending_reads: Vec<Option<(usize, ResourceStates, Option<usize>)>> = vec![None; subresource_count];
+        let mut last_use: Vec<Option<usize>> = vec![None; subresource_count];
+        let mut aggregate_state = vec![TransitionFlags::empty(); self.resources.len()];
+
+        self.renderpass_transitions.resize(self.renderpasses.len(), Vec::new());
+        self.renderpass_clears.resize(self.renderpasses.len(), Vec::new());
+        self.final_transitions.resize(self.resources.len(), Vec::new());
+        self.final_barriers.clear();
+        self.resource_aliasing.clear();
+        self.resource_aliasing.resize(self.resources.len(), false);
+
+        // imported resources already live in memory of their own and start
+        // out in whatever state they were handed over in
+        for import in &self.imports {
+            let idx = import.resource_id as usize;
+            for state in &mut current_states[offsets[idx]..offsets[idx + 1]] {
+                *state = Some(import.state);
+            }
+            self.resource_aliasing[idx] = true;
+        }
+
+        {
+            let split_barriers = self.split_barriers;
+            let transitions = &mut self.renderpass_transitions;
+            let clears = &mut self.renderpass_clears;
+            let mut transition = |pass: usize, resource: usize, subresource: u32, from: Option<ResourceStates>, to: ResourceStates, last_use: Option<usize>| {
+                // the whole resource is placed in the state of whichever
+                // subresource got there first, the others start out in it too
+                let from = match (from, initial_states[resource]) {
+                    (Some(from), _) => Some(from),
+                    (None, Some(initial)) if initial != to => Some(initial),
+                    _ => None
+                };
+
+                if initial_states[resource].is_none() {
+                    initial_states[resource] = Some(to);
+                }
+
+                if let Some(from) = from {
+                    if from != to || to == ResourceStates::UNORDERED_ACCESS {
+                        // only worth splitting with at least one pass in between
+                        let begin = match last_use {
+                            Some(last_use) if split_barriers && from != to && last_use + 1 < pass => Some(last_use),
+                            _ => None
+                        };
+
+                        transitions[pass].push(ResourceTransition {
+                            resource: resource as u32,
+                            subresource,
+                            from,
+                            to,
+                            begin
+                        });
+                    }
+                }
+            };
+
+            for (i, pass) in self.renderpasses.iter().enumerate() {
+                for &(resource, access, range) in &pass.resources {
+                    let idx = resource as usize;
+                    let (mips, slices) = self.resources[idx].subresources();
+
+                    if aggregate_state[idx].is_empty() && self.resources[idx].clear.is_some() {
+                        clears[i].push(resource);
+                    }
+
+                    aggregate_state[idx].insert(access);
+
+                    let state = access.into_queue_state(pass.queue);
+
+                    for subresource in range.indices(mips, slices) {
+                        let sub = offsets[idx] + subresource as usize;
+                        let prev_use = last_use[sub];
+                        last_use[sub] = Some(i);
+
+                        if access.has_write() {
+                            if let Some((pass_idx, reads, read_use)) = pending_reads[sub].take() {
+                                transition(pass_idx, idx, subresource, current_states[sub], reads, read_use);
+                                current_states[sub] = Some(reads);
+                            }
+
+                            transition(i, idx, subresource, current_states[sub], state, prev_use);
+                            current_states[sub] = Some(state);
+                        } else {
+                            pending_reads[sub] = match pending_reads[sub] {
+                                Some((pass_idx, reads, read_use)) => Some((pass_idx, reads | state, read_use)),
+                                None => Some((i, state, prev_use))
+                            };
+                        }
+                    }
+                }
+            }
+
+            for idx in 0..self.resources.len() {
+                for sub in offsets[idx]..offsets[idx + 1] {
+                    let subresource = (sub - offsets[idx]) as u32;
+                    if let Some((pass_idx, reads, read_use)) = pending_reads[sub].take() {
+                        transition(pass_idx, idx, subresource, current_states[sub], reads, read_use);
+                        current_states[sub] = Some(reads);
+                    }
+                }
+            }
+        }
+
+        for idx in 0..self.resources.len() {
+            // untouched subresources are still in the state the resource was
+            // placed in
+            let initial = initial_states[idx];
+            self.final_transitions[idx] = (offsets[idx]..offsets[idx + 1])
+                .filter_map(|sub| current_states[sub].or(initial))
+                .collect();
+        }
+
+        for import in &self.imports {
+            let idx = import.resource_id as usize;
+
+            // handed back every frame, so nothing carries over
+            let current = ::std::mem::take(&mut self.final_transitions[idx]);
+            for sub in 0..offsets[idx + 1] - offsets[idx] {
+                let state = current.get(sub).cloned().unwrap_or(import.state);
+                if state != import.final_state {
+                    self.final_barriers.push(ResourceTransition {
+                        resource: import.resource_id,
+                        subresource: sub as u32,
+                        from: state,
+                        to: import.final_state,
+                        begin: None
+                    });
+                }
+            }
+        }
+
+        let counts = (0..self.resources.len()).map(|idx| (offsets[idx + 1] - offsets[idx]) as u32).collect::<Vec<_>>();
+        for transitions in &mut self.renderpass_transitions {
+            collapse_subresources(transitions, &counts);
+        }
+        collapse_subresources(&mut self.final_barriers, &counts);
+
+        for (idx, resource) in self.resources.iter_mut().enumerate() {
+            resource.desc.flags = aggregate_state[idx].into_resource_flags();
+            resource.initial_state = initial_states[idx].unwrap_or(ResourceStates::COMMON);
+        }
+    }
+
+    pub fn compile(&mut self) -> Result<CompileReport, FrameGraphError> {
+        let start = Instant::now();
+
+        self.validate()?;
+        self.disable_passes();
+
+        let hash = if self.retained { self.structural_hash() } else { 0 };
+        if let Some(entry) = self.compiled.iter().position(|compiled| compiled.hash == hash) {
+            let compiled = self.compiled.remove(entry);
+            self.restore(&compiled);
+            self.cache_stats.hits += 1;
+
+            self.find_history_clears();
+            self.find_render_pass_ops();
+
+            let now = Instant::now();
+            self.place_resources();
+
+            let timings = CompileTimings {
+                placement: now.elapsed(),
+                total: start.elapsed(),
+                ..CompileTimings::default()
+            };
+            self.compile_timings = timings;
+
+            let report = CompileReport {
+                cached: true,
+                timings,
+                ..compiled.report.clone()
+            };
+            self.compiled.insert(0, compiled);
+
+            return Ok(report);
+        }
+
+        self.cache_stats.misses += 1;
+
+        let mut timings = CompileTimings::default();
+
+        let now = Instant::now();
+        let culled = self.cull();
+        if self.renderpasses.is_empty() {
+            return Err(FrameGraphError::EmptyGraph);
+        }
+        timings.cull = now.elapsed();
+
+        let now = Instant::now();
+        let (order, declared_peak_memory, peak_memory) = self.schedule_passes();
+        timings.schedule = now.elapsed();
+
+        let now = Instant::now();
+        self.generate_barriers();
+        timings.barriers = now.elapsed();
+
+        // lifetimes depend on which passes can overlap on different queues
+        self.plan_queues();
+        self.resolve_split_barriers();
+
+        let now = Instant::now();
+        self.find_lifetimes();
+        timings.lifetimes = now.elapsed();
+
+        self.find_history_clears();
+        self.find_render_pass_ops();
+
+        let now = Instant::now();
+        self.place_resources();
+        timings.placement = now.elapsed();
+
+        timings.total = start.elapsed();
+        self.compile_timings = timings;
+
+        let report = CompileReport {
+            culled,
+            cached: false,
+            declared_peak_memory,
+            peak_memory,
+            timings
+        };
+
+        if self.retained {
+            self.compiled.truncate(COMPILED_CACHE_SIZE - 1);
+            self.compiled.insert(0, CompiledGraph {
+                hash,
+                report: report.clone(),
+                order,
+                transitions: self.renderpass_transitions.clone(),
+                clears: self.renderpass_clears.clone(),
+                final_transitions: self.final_transitions.clone(),
+                final_barriers: self.final_barriers.clone(),
+                queue_plan: self.queue_plan.clone(),
+                resources: self.resources.iter().map(|resource| (resource.lifetime, resource.initial_state, resource.desc.flags, resource.culled)).collect()
+            });
+        }
+
+        Ok(report)
+    }
+
+    fn place_resources(&mut self) {
+        // placed resources are looked up (or packed) in the heap cache
+        self.heaps.pack_heap(&mut self.backend, &self.resources, &self.views);
+
+        // imported resources can change every frame (e.g. back buffers), so
+        // their views can't be cached along with the heaps
+        for view in &self.views {
+            if let Some(import) = self.imports.iter().find(|import| import.resource_id == view.resource_id) {
+                self.backend.create_view(import.resource, view.view_id, &view.desc);
+            }
+        }
+    }
+
+    // `None` for culled resources
+    fn physical_resource(&self, idx: usize) -> Option<B::Resource> {
+        match self.imports.iter().find(|import| import.resource_id as usize == idx) {
+            Some(import) => Some(import.resource),
+            None => self.heaps.get_placed_resource_ptr(idx)
+        }
+    }
+
+    // records every pass into `list`, ignoring the queues they asked for
+    pub fn exec(&mut self, list: B::CommandList) {
+        let physical = (0..self.resources.len()).map(|idx| self.physical_resource(idx)).collect::<Vec<_>>();
+        self.find_carried_transitions();
+
+        let begins = self.split_barrier_begins(&physical);
+        let base = self.profiler.begin_frame(&mut self.backend, self.frame, self.renderpasses.len());
+
+        let mut queries = Vec::with_capacity(begins.len());
+        let mut cpu = Vec::with_capacity(begins.len());
+        let mut open = Vec::new();
+
+        for (idx, begins) in begins.into_iter().enumerate() {
+            let timestamp = base.map(|base| base + idx as u32 * 2);
+
+            queries.push(timestamp);
+            let commands = self.pass_commands(idx, &physical, false, timestamp, begins);
+            cpu.push(record_commands(&self.backend, &mut self.renderpasses[idx], list, &mut open, &commands));
+        }
+
+        switch_events(&self.backend, list, &mut open, &[]);
+
+        if !self.final_barriers.is_empty() {
+            let barriers = self.final_barriers.iter().map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())).collect::<Vec<_>>();
+
+            self.backend.resource_barrier(list, &barriers);
+        }
+
+        self.heaps.set_placed_resource_states(&self.final_transitions);
+        self.end_profiling(queries, cpu);
+    }
+
+    // records and submits one command list per batch of the queue plan
+    pub fn exec_queues(&mut self) {
+        let physical = (0..self.resources.len()).map(|idx| self.physical_resource(idx)).collect::<Vec<_>>();
+        self.find_carried_transitions();
+
+        let plan = ::std::mem::take(&mut self.queue_plan);
+        let mut begins = self.split_barrier_begins(&physical);
+        let base = self.profiler.begin_frame(&mut self.backend, self.frame, self.renderpasses.len());
+
+        let mut queries = vec![None; self.renderpasses.len()];
+        let mut cpu = vec![Duration::new(0, 0); self.renderpasses.len()];
+
+        // transitions the pass's own queue can't record, along with the
+        // clears of fresh histories that are still render targets
+        let mut batch_barriers = vec![Vec::new(); plan.batches.len()];
+        let mut batch_clears = vec![Vec::new(); plan.batches.len()];
+        for (idx, (pass, transitions)) in self.renderpasses.iter().zip(self.renderpass_transitions.iter()).enumerate() {
+            if let Some(batch) = plan.transition_batch[idx] {
+                batch_barriers[batch].extend(self.carried_transitions[idx].drain(..).map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())));
+                batch_barriers[batch].extend(transitions.iter()
+                    .filter(|transition| !pass.queue.supports(transition.from | transition.to))
+                    .map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())));
+                batch_clears[batch].extend(self.history_clears[idx].iter().cloned());
+            }
+        }
+
+        let last_graphics = plan.batches.iter().rposition(|batch| batch.queue == QueueType::Graphics);
+        let mut started = [false; 3];
+
+        // transitions out of last frame's states that the first pass's queue
+        // can't record and that no graphics batch precedes (the layout ran a
+        // different graph last) get a list of their own ahead of everything
+        let mut prologue = Vec::new();
+        for (pass, carried) in self.renderpasses.iter().zip(self.carried_transitions.iter_mut()) {
+            let queue = pass.queue;
+
+            prologue.extend(carried.iter()
+                .filter(|transition| !queue.supports(transition.from | transition.to))
+                .map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())));
+            carried.retain(|transition| queue.supports(transition.from | transition.to));
+        }
+
+        if !prologue.is_empty() {
+            let graphics = QueueType::Graphics.index();
+            let list = self.backend.begin_commands(QueueType::Graphics);
+            self.backend.resource_barrier(list, &prologue);
+
+            // last frame's work on the other queues may still use the memory
+            let waits = [QueueType::Compute, QueueType::Copy].iter()
+                .filter(|queue| self.fence_values[queue.index()] > 0)
+                .map(|&queue| (queue, self.fence_values[queue.index()]))
+                .collect::<Vec<_>>();
+
+            // the plan's fence values are relative to the prologue's now
+            self.fence_values[graphics] += 1;
+            self.backend.submit_commands(QueueType::Graphics, list, &waits, Some(self.fence_values[graphics]));
+            started[graphics] = true;
+        }
+
+        for (batch_idx, batch) in plan.batches.iter().enumerate() {
+            let queue = batch.queue.index();
+            let list = self.backend.begin_commands(batch.queue);
+            let mut open = Vec::new();
+
+            for &idx in &batch.passes {
+                let begins = ::std::mem::take(&mut begins[idx]);
+
+                // copy lists need timestamps from a query heap of their own
+                queries[idx] = base.filter(|_| batch.queue != QueueType::Copy).map(|base| base + idx as u32 * 2);
+                let commands = self.pass_commands(idx, &physical, plan.transition_batch[idx].is_some(), queries[idx], begins);
+                cpu[idx] = record_commands(&self.backend, &mut self.renderpasses[idx], list, &mut open, &commands);
+            }
+
+            switch_events(&self.backend, list, &mut open, &[]);
+
+            for &(view_id, color) in &batch_clears[batch_idx] {
+                self.backend.clear_render_target(list, view_id, color);
+            }
+
+            if !batch_barriers[batch_idx].is_empty() {
+                self.backend.resource_barrier(list, &batch_barriers[batch_idx]);
+            }
+
+            // imported resources are handed back on the graphics queue
+            if Some(batch_idx) == last_graphics && !self.final_barriers.is_empty() {
+                let barriers = self.final_barriers.iter().map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())).collect::<Vec<_>>();
+
+                self.backend.resource_barrier(list, &barriers);
+            }
+
+            let mut waits = batch.waits.iter().map(|&(wait_queue, value)| (wait_queue, self.fence_values[wait_queue.index()] + value)).collect::<Vec<_>>();
+
+            // the first batch of every queue also waits for last frame's work
+            // on the others, which may still be using the same memory
+            if !started[queue] {
+                started[queue] = true;
+
+                for &other in &[QueueType::Graphics, QueueType::Compute, QueueType::Copy] {
+                    if other != batch.queue && self.fence_values[other.index()] > 0 && !waits.iter().any(|&(q, _)| q == other) {
+                        waits.push((other, self.fence_values[other.index()]));
+                    }
+                }
+            }
+
+            // the last batch of every queue signals, so the next frame has
+            // something to wait on
+            let last = !plan.batches[batch_idx + 1..].iter().any(|other| other.queue == batch.queue);
+            let signal = if batch.signal || last {
+                Some(self.fence_values[queue] + batch.fence_value)
+            } else {
+                None
+            };
+
+            self.backend.submit_commands(batch.queue, list, &waits, signal);
+        }
+
+        let mut frame_values = [0u64; 3];
+        for batch in &plan.batches {
+            frame_values[batch.queue.index()] = batch.fence_value;
+        }
+
+        for (value, frame_value) in self.fence_values.iter_mut().zip(&frame_values) {
+            *value += frame_value;
+        }
+
+        self.heaps.set_placed_resource_states(&self.final_transitions);
+
+        self.queue_plan = plan;
+        self.end_profiling(queries, cpu);
+    }
+
+    // records the passes into `chunks` command lists from as many threads,
+    // ignoring the queues they asked for, returns the lists in the order they
+    // have to be submitted in. The backend's lists and resources are handed
+    // to the recording threads, so they have to be safe to send
+    pub fn exec_parallel(&mut self, chunks: usize) -> Vec<B::CommandList>
+        where B: Sync,
+              B::Resource: Send + Sync,
+              B::CommandList: Send
+    {
+        let physical = (0..self.resources.len()).map(|idx| self.physical_resource(idx)).collect::<Vec<_>>();
+        self.find_carried_transitions();
+
+        let begins = self.split_barrier_begins(&physical);
+        let base = self.profiler.begin_frame(&mut self.backend, self.frame, self.renderpasses.len());
+
+        let commands = begins.into_iter().enumerate()
+            .map(|(idx, begins)| {
+                let timestamp = base.map(|base| base + idx as u32 * 2);
+
+                self.pass_commands(idx, &physical, false, timestamp, begins)
+            })
+            .collect::<Vec<_>>();
+
+        let chunk_size = self.renderpasses.len().div_ceil(chunks.max(1)).max(1);
+        let list_count = self.renderpasses.len().div_ceil(chunk_size).max(1);
+        let lists = (0..list_count).map(|_| self.backend.begin_commands(QueueType::Graphics)).collect::<Vec<_>>();
+
+        let cpu = {
+            let backend = &self.backend;
+            let renderpasses = &mut self.renderpasses;
+
+            ::std::thread::scope(|scope| {
+                let threads = renderpasses.chunks_mut(chunk_size).zip(commands.chunks(chunk_size)).zip(&lists).map(|((passes, commands), &list)| {
+                    scope.spawn(move || {
+                        let mut open = Vec::new();
+                        let cpu = passes.iter_mut().zip(commands).map(|(pass, commands)| record_commands(backend, pass, list, &mut open, commands)).collect::<Vec<_>>();
+
+                        switch_events(backend, list, &mut open, &[]);
+                        cpu
+                    })
+                }).collect::<Vec<_>>();
+
+                threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect::<Vec<_>>()
+            })
+        };
+
+        if !self.final_barriers.is_empty() {
+            let barriers = self.final_barriers.iter().map(|transition| transition.to_barrier(&physical, BarrierFlags::empty())).collect::<Vec<_>>();
+
+            self.backend.resource_barrier(lists[lists.len() - 1], &barriers);
+        }
+
+        self.heaps.set_placed_resource_states(&self.final_transitions);
+
+        let queries = commands.iter().map(|commands| commands.timestamp).collect();
+        self.end_profiling(queries, cpu);
+
+        lists
+    }
+
+    // hands what was recorded this frame to the profiler, `queries` and `cpu`
+    // are by pass
+    fn end_profiling(&mut self, queries: Vec<Option<u32>>, cpu: Vec<Duration>) {
+        if !self.profiler.enabled() {
+            return;
+        }
+
+        let passes = self.renderpasses.iter().zip(cpu).map(|(pass, cpu)| PassTiming {
+            name: pass.name.clone(),
+            subgraph: pass.subgraph.clone(),
+            gpu: None,
+            cpu
+        }).collect();
+
+        self.profiler.end_frame(FrameTimings {
+            frame: self.frame,
+            compile: self.compile_timings,
+            passes
+        }, queries);
+    }
+
+    // a placed resource is in the state the last frame using the same layout
+    // left it in, or the one it was placed in, which only matches what the
+    // frame starts from once the layout ran this frame's graph before
+    fn find_carried_transitions(&mut self) {
+        self.carried_transitions.clear();
+        self.carried_transitions.resize(self.renderpasses.len(), Vec::new());
+
+        let mut counts = Vec::with_capacity(self.resources.len());
+        for (idx, resource) in self.resources.iter().enumerate() {
+            let (mips, slices) = resource.subresources();
+            counts.push(mips * slices);
+
+            let first_use = match self.first_use(idx) {
+                Some(first_use) if !resource.imported => first_use,
+                _ => continue
+            };
+
+            for (sub, &state) in self.heaps.get_placed_resource_states(idx).iter().enumerate() {
+                if state != resource.initial_state {
+                    self.carried_transitions[first_use].push(ResourceTransition {
+                        resource: idx as u32,
+                        subresource: sub as u32,
+                        from: state,
+                        to: resource.initial_state,
+                        begin: None
+                    });
+                }
+            }
+        }
+
+        for transitions in &mut self.carried_transitions {
+            collapse_subresources(transitions, &counts);
+        }
+    }
+
+    // begin halves of the split barriers, by the pass they're issued after
+    fn split_barrier_begins(&self, physical: &[Option<B::Resource>]) -> Vec<Vec<ResourceBarrier<B::Resource>>> {
+        let mut begins = vec![Vec::new(); self.renderpasses.len()];
+
+        for transitions in &self.renderpass_transitions {
+            for transition in transitions {
+                if let Some(begin) = transition.begin {
+                    begins[begin].push(transition.to_barrier(physical, BarrierFlags::BEGIN_ONLY));
+                }
+            }
+        }
+
+        begins
+    }
+
+    // works out everything recorded for a pass besides its exec closure,
+    // has to run in schedule order as aliasing depends on the passes before
+    fn pass_commands(&mut self, idx: usize, physical: &[Option<B::Resource>], hoisted: bool, timestamp: Option<u32>, begins: Vec<ResourceBarrier<B::Resource>>) -> PassCommands<B::Resource> {
+        let pass = &self.renderpasses[idx];
+        let mut barriers = Vec::new();
+
+        for &resource in &pass.bound {
+            let idx = resource as usize;
+            if !self.resource_aliasing[idx] {
+                barriers.push(ResourceBarrier::Alias(None, physical[idx].unwrap()));
+
+                self.resource_aliasing[idx] = true;
+            }
+        }
+
+        barriers.extend(self.carried_transitions[idx].iter().map(|transition| transition.to_barrier(physical, BarrierFlags::empty())));
+
+        // hoisted transitions were already recorded on the graphics queue
+        let queue = pass.queue;
+        barriers.extend(self.renderpass_transitions[idx].iter()
+            .filter(|transition| !hoisted || queue.supports(transition.from | transition.to))
+            .map(|transition| {
+                let flags = if transition.begin.is_some() { BarrierFlags::END_ONLY } else { BarrierFlags::empty() };
+
+                transition.to_barrier(physical, flags)
+            }));
+
+        let render_pass = self.render_pass_ops.get(idx).cloned().and_then(|ops| ops);
+
+        // attachments are cleared or discarded by their load op instead
+        let attached = |resource: u32| render_pass.as_ref().is_some_and(|ops| {
+            ops.render_targets.iter().chain(ops.depth.iter()).any(|&(res, _)| res == resource)
+        });
+
+        let clears = self.renderpass_clears[idx].iter().filter(|&&resource| !attached(resource)).map(|&resource| {
+            let clear = self.resources[resource as usize].clear.unwrap();
+
+            match (clear.state, clear.value) {
+                (InitialResourceState::DontCare, _) => PassClear::Discard(physical[resource as usize].unwrap()),
+                (InitialResourceState::Clear, ClearValue::Color(color)) => PassClear::RenderTarget(clear.view_id, color),
+                (InitialResourceState::Clear, ClearValue::DepthStencil(depth, stencil)) => PassClear::DepthStencil(clear.view_id, depth, stencil)
+            }
+        }).collect();
+
+        // a hoisted pass's histories are cleared along with its transitions
+        let history_clears = if hoisted { Vec::new() } else {
+            self.history_clears[idx].iter().map(|&(view_id, color)| PassClear::RenderTarget(view_id, color)).collect()
+        };
+
+        PassCommands {
+            history_clears,
+            barriers,
+            clears,
+            render_pass,
+            timestamp,
+            begins
+        }
+    }
+
+    pub fn finish(&mut self) {
+        assert!(self.events.is_empty(), "begin_event without a matching end_event");
+
+        self.renderpasses.clear();
+        self.culled_passes.clear();
+        self.renderpass_transitions.clear();
+        self.renderpass_clears.clear();
+        self.carried_transitions.clear();
+        self.resources.clear();
+        self.imports.clear();
+        self.views.clear();
+        self.outputs.clear();
+        self.errors.clear();
+
+        for history in &mut self.histories {
+            if history.bound.take().is_some() {
+                ::std::mem::swap(&mut history.current, &mut history.previous);
+                history.fresh = false;
+            }
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+        self.virtual_offset = 0;
+        self.virtual_view = 0;
+    }
+}
+
+
+// created: Vec<(&'static str, u32, TransitionFlags, ResourceDesc)>,
+//#[derive(Debug)]
+pub struct PlacedResource {
+    name: String,
+    resource_id: u32,
+    flags: TransitionFlags,
+    desc: ResourceDesc,
+    clear: Option<ResourceClear>,
+}
+
+//#[derive(Debug)]
+#[derive(Clone, Hash)]
+pub struct ResourceView {
+    pub resource_id: u32,
+    pub view_id: u32,
+    pub desc: ResourceViewDesc
+}
+
+//#[derive(Debug)]
+pub struct FrameGraphBuilder {
+    created: Vec<PlacedResource>,
+    resources: Vec<(u32, TransitionFlags, SubresourceRange)>,
+    views: Vec<ResourceView>,
+    counter: u32,
+    view_counter: u32,
+    resolution: (u32, u32),
+    queue: QueueType,
+    side_effects: bool,
+    frame: u32,
+    // prepended to the names of created resources, "Bloom/" in sub-graph
+    // "Bloom"
+    prefix: String,
+    stale: Vec<String>,
+    histories: Vec<HistoryRequest>,
+    // (from, to) resource ids, readers of `to` read `from` while the pass is
+    // disabled
+    passthroughs: Vec<(u32, u32)>,
+    // clears declared on resources the pass didn't create
+    clears: Vec<(u32, ResourceClear)>,
+    // (from, to) names of passthroughs that don't fit together
+    mismatched: Vec<(String, String)>,
+}
+
+struct HistoryRequest {
+    name: String,
+    desc: ResourceDesc,
+    clear_color: [f32; 4],
+    clear: ResourceClear,
+    current: u32,
+    previous: u32,
+    // render target view of `previous`, used to clear it while it's fresh
+    clear_view: u32
+}
+
+impl FrameGraphBuilder {
+    fn new(offset: u32, view_offset: u32, resolution: (u32, u32), frame: u32, scope: &str) -> Self {
+        FrameGraphBuilder {
+            created: Vec::new(),
+            resources: Vec::new(),
+            views: Vec::new(),
+            counter: offset,
+            view_counter: view_offset,
+            resolution,
+            queue: QueueType::Graphics,
+            side_effects: false,
+            frame,
+            prefix: if scope.is_empty() { String::new() } else { format!("{}/", scope) },
+            stale: Vec::new(),
+            histories: Vec::new(),
+            passthroughs: Vec::new(),
+            clears: Vec::new(),
+            mismatched: Vec::new()
+        }
+    }
+
+    fn scoped_name<N: Into<String>>(&self, name: N) -> String {
+        format!("{}{}", self.prefix, name.into())
+    }
+
+    // queue the pass runs on with `FrameGraph::exec_queues`
+    pub fn set_queue(&mut self, queue: QueueType) {
+        self.queue = queue;
+    }
+
+    // keeps the pass even if nothing reads what it writes
+    pub fn set_side_effects(&mut self, side_effects: bool) {
+        self.side_effects = side_effects;
+    }
+
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    // while the pass is disabled, later passes using `to` use `from` instead,
+    // e.g. the unprocessed input of an effect that's turned off. Writes to
+    // `to` land in `from` then, the two are the same resource
+    pub fn passthrough<T: ResourceBinding, U: ResourceBinding>(&mut self, from: &T, to: &U) {
+        let from = from.get_virtual_resources();
+        let to = to.get_virtual_resources();
+
+        if from.len() != to.len() {
+            let names = |resources: &[FrameGraphResource]| resources.iter().map(|res| &res.name[..]).collect::<Vec<_>>().join(", ");
+            self.mismatched.push((names(&from), names(&to)));
+
+            return;
+        }
+
+        for (from, to) in from.into_iter().zip(to) {
+            if from.format != to.format || from.buffer != to.buffer {
+                self.mismatched.push((from.name.to_string(), to.name.to_string()));
+                continue;
+            }
+
+            self.passthroughs.push((from.resource_id, to.resource_id));
+        }
+    }
+
+    pub fn create_render_target<N: Into<String>>(&mut self, name: N, desc: RenderTargetDesc) -> RenderTargetResource {
+        let name = self.scoped_name(name);
+        let virtual_id = self.counter;
+        self.counter += 1;
+        let res = FrameGraphResource {
+            name: name.as_str().into(),
+            format: desc.format.into(),
+            buffer: None,
+            array_size: desc.array_size.max(1),
+            view_id: self.view_counter,
+            resource_id: virtual_id,
+            frame: self.frame
+        };
+
+        let (width, height) = desc.size.resolve(self.resolution);
+        let resource_desc = ResourceDesc {
+            dimension: ResourceDimension::Texture2D,
+            width: width as u64,
+            height,
+            depth_or_array_size: desc.array_size.max(1) as u16,
+            mip_levels: desc.mip_levels as u16,
+            format: desc.format.into(),
+            flags: ResourceFlags::empty(),
+        };
+
+        self.views.push(ResourceView {
+            resource_id: virtual_id,
+            view_id: self.view_counter,
+            desc: ResourceViewDesc::RenderTarget {
+                format: desc.format.into(),
+                mip_slice: 0,
+                first_array_slice: 0,
+                array_size: res.array_size
+            }
+        });
+
+        self.view_counter += 1;
+
+        self.created.push(PlacedResource {
+            resource_id: virtual_id,
+            flags: TransitionFlags::RENDER_TARGET,
+            desc: resource_desc,
+            name,
+            clear: Some(ResourceClear {
+                view_id: res.view_id,
+                value: ClearValue::Color(desc.clear_color),
+                state: desc.state
+            })
+        });
+        let res = self.write(res, TransitionFlags::RENDER_TARGET, SubresourceRange::all());
+
+        RenderTargetResource(res)
+    }
+
+    // a render target that survives the frame, returns the one written this
+    // frame and the one written last frame (cleared to `clear_color` on the
+    // first frame and after resizes), they swap every frame
+    pub fn create_history<N: Into<String>>(&mut self, name: N, desc: RenderTargetDesc) -> (RenderTargetResource, ShaderResource) {
+        let name = self.scoped_name(name);
+        let (current, previous) = (self.counter, self.counter + 1);
+        self.counter += 2;
+
+        let (width, height) = desc.size.resolve(self.resolution);
+        let resource_desc = ResourceDesc {
+            dimension: ResourceDimension::Texture2D,
+            width: width as u64,
+            height,
+            depth_or_array_size: desc.array_size.max(1) as u16,
+            mip_levels: desc.mip_levels as u16,
+            format: desc.format.into(),
+            flags: ResourceFlags::ALLOW_RENDER_TARGET,
+        };
+
+        let shared: Arc<str> = name.as_str().into();
+        let resource = |id, view_id| FrameGraphResource {
+            name: shared.clone(),
+            format: desc.format.into(),
+            buffer: None,
+            array_size: desc.array_size.max(1),
+            view_id,
+            resource_id: id,
+            frame: self.frame
+        };
+
+        let write = resource(current, self.view_counter);
+        let clear_view = self.view_counter + 1;
+        let read = resource(previous, self.view_counter + 2);
+        self.view_counter += 3;
+
+        for &(resource_id, view_id) in &[(current, write.view_id), (previous, clear_view)] {
+            self.views.push(ResourceView {
+                resource_id,
+                view_id,
+                desc: ResourceViewDesc::RenderTarget {
+                    format: desc.format.into(),
+                    mip_slice: 0,
+                    first_array_slice: 0,
+                    array_size: read.array_size
+                }
+            });
+        }
+
+        self.views.push(ResourceView {
+            resource_id: previous,
+            view_id: read.view_id,
+            desc: ResourceViewDesc::ShaderResource {
+                format: read.format.shader_resource_format(),
+                most_detailed_mip: 0,
+                mip_levels: ALL_MIPS,
+                first_array_slice: 0,
+                array_size: read.array_size
+            }
+        });
+
+        self.histories.push(HistoryRequest {
+            name,
+            desc: resource_desc,
+            clear_color: desc.clear_color,
+            clear: ResourceClear {
+                view_id: write.view_id,
+                value: ClearValue::Color(desc.clear_color),
+                state: desc.state
+            },
+            current,
+            previous,
+            clear_view
+        });
+
+        let write = self.write(write, TransitionFlags::RENDER_TARGET, SubresourceRange::all());
+        let read = self.read(read, TransitionFlags::SHADER_RESOURCE, SubresourceRange::all());
+
+        (RenderTargetResource(write), ShaderResource(read))
+    }
+
+    pub fn create_depth<N: Into<String>>(&mut self, name: N, desc: DepthDesc) -> DepthWriteResource {
+        let name = self.scoped_name(name);
+        let virtual_id = self.counter;
+        self.counter += 1;
+        let res = FrameGraphResource {
+            name: name.as_str().into(),
+            format: desc.format.into(),
+            buffer: None,
+            array_size: desc.array_size.max(1),
+            view_id: self.view_counter,
+            resource_id: virtual_id,
+            frame: self.frame
+        };
+
+        self.views.push(ResourceView {
+            resource_id: virtual_id,
+            view_id: self.view_counter,
+            desc: ResourceViewDesc::DepthStencil {
+                format: desc.format.into(),
+                mip_slice: 0,
+                first_array_slice: 0,
+                array_size: res.array_size,
+                read_only: false
+            }
+        });
+
+        self.view_counter += 1;
+
+        let (width, height) = desc.size.resolve(self.resolution);
+        let resource_desc = ResourceDesc {
+            dimension: ResourceDimension::Texture2D,
+            width: width as u64,
+            height,
+            depth_or_array_size: desc.array_size.max(1) as u16,
+            mip_levels: desc.mip_levels as u16,
+            format: desc.format.into(),
+            flags: ResourceFlags::empty(),
+        };
+
+        self.created.push(PlacedResource {
+            resource_id: virtual_id,
+            flags: TransitionFlags::DEPTH_WRITE,
+            desc: resource_desc,
+            name,
+            clear: Some(ResourceClear {
+                view_id: res.view_id,
+                value: ClearValue::DepthStencil(desc.clear_depth, desc.clear_stencil),
+                state: desc.state
+            })
+        });
+        let res = self.write(res, TransitionFlags::DEPTH_WRITE, SubresourceRange::all());
+
+        DepthWriteResource(res)
+    }
+
+    // the view is of the first mip, `read_write_uav_range` views the others.
+    // nothing reads a uav before a pass writes it, so there's no initial
+    // state to ask for
+    pub fn create_uav<N: Into<String>>(&mut self, name: N, desc: UnorderedAccessDesc) -> UnorderedAccessResource {
+        let name = self.scoped_name(name);
+        let virtual_id = self.counter;
+        self.counter += 1;
+        let res = FrameGraphResource {
+            name: name.as_str().into(),
+            format: desc.format.into(),
+            buffer: None,
+            array_size: desc.array_size.max(1),
+            view_id: self.view_counter,
+            resource_id: virtual_id,
+            frame: self.frame
+        };
+
+        let (width, height) = desc.size.resolve(self.resolution);
+        let resource_desc = ResourceDesc {
+            dimension: ResourceDimension::Texture2D,
+            width: width as u64,
+            height,
+            depth_or_array_size: desc.array_size.max(1) as u16,
+            mip_levels: desc.mip_levels as u16,
+            format: desc.format.into(),
+            flags: ResourceFlags::empty(),
+        };
+
+        self.views.push(ResourceView {
+            resource_id: virtual_id,
+            view_id: self.view_counter,
+            desc: ResourceViewDesc::UnorderedAccess {
+                format: desc.format.into(),
+                mip_slice: 0,
+                first_array_slice: 0,
+                array_size: res.array_size
+            }
+        });
+
+        self.view_counter += 1;
+
+        self.created.push(PlacedResource {
+            resource_id: virtual_id,
+            flags: TransitionFlags::UNORDERED_ACCESS,
+            desc: resource_desc,
+            name,
+            clear: None
+        });
+        let res = self.write(res, TransitionFlags::UNORDERED_ACCESS, SubresourceRange::all());
+
+        UnorderedAccessResource(res)
+    }
+
+    pub fn read_write_uav<T: IntoTypedResource<UnorderedAccessResource>>(&mut self, resource: T) -> UnorderedAccessResource {
+        self.read_write_uav_range(resource, SubresourceRange::all())
+    }
+
+    // the view is of `range.first_mip`, the rest of the range is only tracked
+    pub fn read_write_uav_range<T: IntoTypedResource<UnorderedAccessResource>>(&mut self, resource: T, range: SubresourceRange) -> UnorderedAccessResource {
+        let mut res = resource.get_virtual_resource();
+
+        // the resource may not have been created with a uav, give it one
+        let (first_array_slice, array_size) = range.slice_range(res.array_size);
+        res.view_id = self.view_counter;
+        self.views.push(ResourceView {
+            resource_id: res.resource_id,
+            view_id: res.view_id,
+            desc: ResourceViewDesc::UnorderedAccess {
+                format: res.format,
+                mip_slice: range.first_mip,
+                first_array_slice,
+                array_size
+            }
+        });
+
+        self.view_counter += 1;
+
+        UnorderedAccessResource(self.write(res, TransitionFlags::UNORDERED_ACCESS, range))
+    }
+
+    pub fn create_buffer<N: Into<String>>(&mut self, name: N, desc: BufferDesc) -> BufferUnorderedAccessResource {
+        let name = self.scoped_name(name);
+        let virtual_id = self.counter;
+        self.counter += 1;
+        let res = FrameGraphResource {
+            name: name.as_str().into(),
+            format: desc.format.view_format(),
+            buffer: Some(desc),
+            array_size: 1,
+            view_id: self.view_counter,
+            resource_id: virtual_id,
+            frame: self.frame
+        };
+
+        let resource_desc = ResourceDesc {
+            dimension: ResourceDimension::Buffer,
+            width: desc.format.element_size() as u64 * desc.element_count as u64,
+            height: 1,
+            depth_or_array_size: 1,
+            mip_levels: 1,
+            format: Format::Unknown,
+            flags: ResourceFlags::empty(),
+        };
+
+        self.views.push(ResourceView {
+            resource_id: virtual_id,
+            view_id: self.view_counter,
+            desc: desc.unordered_access_view()
+        });
+
+        self.view_counter += 1;
+
+        self.created.push(PlacedResource {
+            resource_id: virtual_id,
+            flags: TransitionFlags::UNORDERED_ACCESS,
+            desc: resource_desc,
+            name,
+            clear: None
+        });
+        let res = self.write(res, TransitionFlags::UNORDERED_ACCESS, SubresourceRange::all());
+
+        BufferUnorderedAccessResource(res)
+    }
+
+    pub fn read_buffer<T: IntoTypedResource<BufferShaderResource>>(&mut self, resource: &T) -> BufferShaderResource {
+        let mut res = resource.get_virtual_resource();
+        let desc = res.buffer.expect("buffer handle without a buffer description");
+
+        res.view_id = self.view_counter;
+        self.views.push(ResourceView {
+            resource_id: res.resource_id,
+            view_id: res.view_id,
+            desc: desc.shader_resource_view()
+        });
+
+        self.view_counter += 1;
+
+        BufferShaderResource(self.read(res, TransitionFlags::SHADER_RESOURCE, SubresourceRange::all()))
+    }
+
+    pub fn read_write_buffer<T: IntoTypedResource<BufferUnorderedAccessResource>>(&mut self, resource: T) -> BufferUnorderedAccessResource {
+        BufferUnorderedAccessResource(self.write(resource.get_virtual_resource(), TransitionFlags::UNORDERED_ACCESS, SubresourceRange::all()))
+    }
+
+    pub fn write_render_target<T: IntoTypedResource<RenderTargetResource>>(&mut self, resource: T) -> RenderTargetResource {
+        self.write_render_target_range(resource, SubresourceRange::all())
+    }
+
+    // the view is of `range.first_mip`, the rest of the range is only tracked
+    pub fn write_render_target_range<T: IntoTypedResource<RenderTargetResource>>(&mut self, resource: T, range: SubresourceRange) -> RenderTargetResource {
+        let mut res = resource.get_virtual_resource();
+        let (first_array_slice, array_size) = range.slice_range(res.array_size);
+
+        res.view_id = self.view_counter;
+        self.views.push(ResourceView {
+            resource_id: res.resource_id,
+            view_id: res.view_id,
+            desc: ResourceViewDesc::RenderTarget {
+                format: res.format,
+                mip_slice: range.first_mip,
+                first_array_slice,
+                array_size
+            }
+        });
+
+        self.view_counter += 1;
+
+        RenderTargetResource(self.write(res, TransitionFlags::RENDER_TARGET, range))
+    }
+
+    // like `write_render_target`, but the target is cleared to `clear_color`
+    // before the first pass using it this frame, e.g. an imported back buffer
+    pub fn clear_render_target<T: IntoTypedResource<RenderTargetResource>>(&mut self, resource: T, clear_color: [f32; 4]) -> RenderTargetResource {
+        let res = self.write_render_target(resource);
+
+        if res.0.frame == self.frame {
+            self.clears.push((res.0.resource_id, ResourceClear {
+                view_id: res.0.view_id,
+                value: ClearValue::Color(clear_color),
+                state: InitialResourceState::Clear
+            }));
+        }
+
+        res
+    }
+
+    pub fn read_srv<T: IntoTypedResource<ShaderResource>>(&mut self, resource: &T) -> ShaderResource {
+        self.read_srv_range(resource, SubresourceRange::all())
+    }
+
+    // e.g. mip N of a chain while the same pass writes mip N + 1
+    pub fn read_srv_range<T: IntoTypedResource<ShaderResource>>(&mut self, resource: &T, range: SubresourceRange) -> ShaderResource {
+        let mut res = resource.get_virtual_resource();
+        let (first_array_slice, array_size) = range.slice_range(res.array_size);
+
+        res.view_id = self.view_counter;
+        self.views.push(ResourceView {
+            resource_id: res.resource_id,
+            view_id: res.view_id,
+            desc: ResourceViewDesc::ShaderResource {
+                format: res.format.shader_resource_format(),
+                most_detailed_mip: range.first_mip,
+                mip_levels: range.mip_levels.unwrap_or(ALL_MIPS),
+                first_array_slice,
+                array_size
+            }
+        });
+
+        self.view_counter += 1;
+
+        ShaderResource(self.read(res, TransitionFlags::SHADER_RESOURCE, range))
+    }
+
+    // depth tests against a read-only view
+    pub fn read_depth<T: IntoTypedResource<DepthReadResource>>(&mut self, resource: &T) -> DepthReadResource {
+        let res = self.depth_stencil_view(resource.get_virtual_resource(), true);
+
+        DepthReadResource(self.read(res, TransitionFlags::DEPTH_READ, SubresourceRange::all()))
+    }
+
+    pub fn write_depth<T: IntoTypedResource<DepthWriteResource>>(&mut self, resource: T) -> DepthWriteResource {
+        let res = self.depth_stencil_view(resource.get_virtual_resource(), false);
+
+        DepthWriteResource(self.write(res, TransitionFlags::DEPTH_WRITE, SubresourceRange::all()))
+    }
+
+    fn depth_stencil_view(&mut self, mut resource: FrameGraphResource, read_only: bool) -> FrameGraphResource {
+        resource.view_id = self.view_counter;
+        self.views.push(ResourceView {
+            resource_id: resource.resource_id,
+            view_id: resource.view_id,
+            desc: ResourceViewDesc::DepthStencil {
+                format: resource.format,
+                mip_slice: 0,
+                first_array_slice: 0,
+                array_size: resource.array_size,
+                read_only
+            }
+        });
+
+        self.view_counter += 1;
+
+        resource
+    }
+
+    fn read(&mut self, resource: FrameGraphResource, transition: TransitionFlags, range: SubresourceRange) -> FrameGraphResource {
+        self.access(resource, transition, range)
+    }
+
+    fn write(&mut self, resource: FrameGraphResource, transition: TransitionFlags, range: SubresourceRange) -> FrameGraphResource {
+        self.access(resource, transition, range)
+    }
+
+    // the ids of stale handles may not exist anymore, they're only kept
+    // around to fail `compile`
+    fn access(&mut self, resource: FrameGraphResource, transition: TransitionFlags, range: SubresourceRange) -> FrameGraphResource {
+        if resource.frame == self.frame {
+            self.resources.push((resource.resource_id, transition, range));
+        } else {
+            self.stale.push(resource.name.to_string());
+        }
+
+        resource
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum TextureSize {
+    Full,
+    Half,
+    Explicit(u32, u32)
+}
+
+impl TextureSize {
+    pub fn resolve(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            TextureSize::Full => (width, height),
+            // round up so odd resolutions keep their last row/column
+            TextureSize::Half => (width.div_ceil(2).max(1), height.div_ceil(2).max(1)),
+            TextureSize::Explicit(width, height) => (width, height)
+        }
+    }
+}
+
+// mips and array slices a pass accesses, counts of `None` extend to the last
+// mip/slice of the resource
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct SubresourceRange {
+    pub first_mip: u32,
+    pub mip_levels: Option<u32>,
+    pub first_slice: u32,
+    pub array_size: Option<u32>
+}
+
+impl SubresourceRange {
+    pub fn all() -> Self {
+        SubresourceRange {
+            first_mip: 0,
+            mip_levels: None,
+            first_slice: 0,
+            array_size: None
+        }
+    }
+
+    // a single mip of every slice
+    pub fn mip(mip: u32) -> Self {
+        SubresourceRange {
+            first_mip: mip,
+            mip_levels: Some(1),
+            ..SubresourceRange::all()
+        }
+    }
+
+    pub fn mips(first_mip: u32, mip_levels: u32) -> Self {
+        SubresourceRange {
+            first_mip,
+            mip_levels: Some(mip_levels),
+            ..SubresourceRange::all()
+        }
+    }
+
+    // every mip of a single slice
+    pub fn slice(slice: u32) -> Self {
+        SubresourceRange {
+            first_slice: slice,
+            array_size: Some(1),
+            ..SubresourceRange::all()
+        }
+    }
+
+    pub fn slices(first_slice: u32, array_size: u32) -> Self {
+        SubresourceRange {
+            first_slice,
+            array_size: Some(array_size),
+            ..SubresourceRange::all()
+        }
+    }
+
+    // (first slice, slice count) of a resource with `array_size` slices
+    fn slice_range(&self, array_size: u32) -> (u32, u32) {
+        let end = self.array_size.map_or(array_size, |count| (self.first_slice + count).min(array_size));
+
+        (self.first_slice, end.saturating_sub(self.first_slice))
+    }
+
+    // subresource indices of a resource with `mip_levels` mips and
+    // `array_size` slices, clamped to the ones it actually has
+    fn indices(&self, mip_levels: u32, array_size: u32) -> Vec<u32> {
+        let mip_end = self.mip_levels.map_or(mip_levels, |count| (self.first_mip + count).min(mip_levels));
+        let slice_end = self.array_size.map_or(array_size, |count| (self.first_slice + count).min(array_size));
+
+        let mut indices = Vec::new();
+        for slice in self.first_slice..slice_end {
+            for mip in self.first_mip..mip_end {
+                indices.push(mip + slice * mip_levels);
+            }
+        }
+
+        indices
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum InitialResourceState {
+    Clear,
+    DontCare
+}
+
+// formats with stencil put it in a second plane, which the per subresource
+// state tracking doesn't know about
+#[derive(Debug, Copy, Clone)]
+pub enum DepthFormat {
+    D32
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DepthDesc {
+    pub format: DepthFormat,
+    pub size: TextureSize,
+    pub mip_levels: u32,
+    // texture array when greater than one, e.g. shadow cascades
+    pub array_size: u32,
+    pub state: InitialResourceState,
+    pub clear_depth: f32,
+    pub clear_stencil: u8,
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum TextureFormat {
+    RGBA8,
+    R8,
+}
+
+impl From<DepthFormat> for Format {
+    fn from(f: DepthFormat) -> Format {
+
+        match f {
+            DepthFormat::D32 => Format::D32Float
+        }
+    }
+}
+
+impl From<TextureFormat> for Format {
+    fn from(f: TextureFormat) -> Format {
+
+        match f {
+            TextureFormat::RGBA8 => Format::R8G8B8A8Unorm,
+            TextureFormat::R8 => Format::R8Unorm
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderTargetDesc {
+    pub format: TextureFormat,
+    pub size: TextureSize,
+    pub mip_levels: u32,
+    // texture array when greater than one
+    pub array_size: u32,
+    pub state: InitialResourceState,
+    pub clear_color: [f32; 4],
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum BufferFormat {
+    // element stride in bytes
+    Structured(u32),
+    Raw,
+    Typed(TextureFormat),
+}
+
+impl BufferFormat {
+    fn element_size(self) -> u32 {
+        match self {
+            BufferFormat::Structured(stride) => stride,
+            BufferFormat::Raw => 4,
+            BufferFormat::Typed(format) => Format::from(format).size() as u32
+        }
+    }
+
+    fn view_format(self) -> Format {
+        match self {
+            BufferFormat::Structured(_) => Format::Unknown,
+            BufferFormat::Raw => Format::R32Typeless,
+            BufferFormat::Typed(format) => format.into()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct BufferDesc {
+    pub format: BufferFormat,
+    pub element_count: u32,
+}
+
+impl BufferDesc {
+    fn view_stride(&self) -> u32 {
+        match self.format {
+            BufferFormat::Structured(stride) => stride,
+            _ => 0
+        }
+    }
+
+    fn shader_resource_view(&self) -> ResourceViewDesc {
+        ResourceViewDesc::BufferShaderResource {
+            format: self.format.view_format(),
+            num_elements: self.element_count,
+            stride: self.view_stride(),
+            raw: self.format == BufferFormat::Raw
+        }
+    }
 
-        for (idx, resource) in self.resources.iter_mut().enumerate() {
-            resource.desc.Flags = aggregate_state[idx].into_resource_flags();
+    fn unordered_access_view(&self) -> ResourceViewDesc {
+        ResourceViewDesc::BufferUnorderedAccess {
+            format: self.format.view_format(),
+            num_elements: self.element_count,
+            stride: self.view_stride(),
+            raw: self.format == BufferFormat::Raw
         }
     }
+}
 
-    pub fn compile(&mut self) {
+#[derive(Debug, Copy, Clone)]
+pub struct UnorderedAccessDesc {
+    pub format: TextureFormat,
+    pub size: TextureSize,
+    pub mip_levels: u32,
+    // texture array when greater than one
+    pub array_size: u32,
+}
 
-        use std::time::Instant;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let now = Instant::now();
-        self.cull();
-        let elapsed = now.elapsed();
-        let sec = (elapsed.as_secs() as f64) + (elapsed.subsec_nanos() as f64 / 1000.0);
-        //println!("Cull: {}us", sec);
+    use recording::{
+        RecordedCommand,
+        RecordingBackend
+    };
 
-        let now = Instant::now();
-        self.find_lifetimes();
-        let elapsed = now.elapsed();
-        let sec = (elapsed.as_secs() as f64) + (elapsed.subsec_nanos() as f64 / 1000.0);
-        //println!("Lifetimes: {}us", sec);
+    fn graph() -> FrameGraph<RecordingBackend> {
+        let mut fg = FrameGraph::new(RecordingBackend::new());
+        fg.set_resolution(64, 64);
 
-        let now = Instant::now();
-        self.generate_barriers();
-        let elapsed = now.elapsed();
-        let sec = (elapsed.as_secs() as f64) + (elapsed.subsec_nanos() as f64 / 1000.0);
-        //println!("GenBarriers: {}us", sec);
+        fg
+    }
 
-        let now = Instant::now();
-        let mem = self.heaps.pack_heap(&self.resources, &self.views);
-        let elapsed = now.elapsed();
-        let sec = (elapsed.as_secs() as f64) + (elapsed.subsec_nanos() as f64 / 1000.0);
-        //println!("PackHeaps: {}us", sec);
+    fn target(state: InitialResourceState, clear_color: [f32; 4]) -> RenderTargetDesc {
+        RenderTargetDesc {
+            format: TextureFormat::RGBA8,
+            size: TextureSize::Full,
+            mip_levels: 1,
+            array_size: 1,
+            state,
+            clear_color
+        }
     }
 
-    pub fn exec(&mut self, list: *mut ID3D12GraphicsCommandList) {
-        let entry = self.heaps.current();
+    fn depth() -> DepthDesc {
+        DepthDesc {
+            format: DepthFormat::D32,
+            size: TextureSize::Full,
+            mip_levels: 1,
+            array_size: 1,
+            state: InitialResourceState::Clear,
+            clear_depth: 1.0,
+            clear_stencil: 0
+        }
+    }
 
-        //println!("{}", "exec!");
-        for (pass, trans) in self.renderpasses.iter_mut().zip(self.renderpass_transitions.iter()) {
-            let mut barriers = Vec::new();
-            let mut data = vec![0xfeu8; pass.param_size];
-            let mut cur = 0;
+    fn back_buffer(fg: &mut FrameGraph<RecordingBackend>) -> ImportedResource {
+        fg.import_resource("BackBuffer", 1000, TextureFormat::RGBA8, ResourceStates::PRESENT, ResourceStates::PRESENT)
+    }
 
-            unsafe {
-                for &(b, id, res_id) in &pass.params {
-                    let idx = res_id as usize;
-                    if !self.resource_aliasing[idx] {
-                        let res = self.heaps.get_placed_resource_ptr(idx);
-                        barriers.push(ResourceBarrier::Alias(::std::ptr::null_mut(), res).into());
+    // (load, store) of every attachment of a pass, depth last
+    fn attachments(fg: &FrameGraph<RecordingBackend>, pass: usize) -> Vec<(LoadOp, StoreOp)> {
+        let ops = fg.render_pass_ops(pass).expect("pass has no render pass");
 
-                        self.resource_aliasing[idx] = true;
-                    }
+        ops.render_targets.iter().chain(ops.depth.iter()).map(|&(_, attachment)| (attachment.load, attachment.store)).collect()
+    }
 
-                    let sz = if b {
-                        let handle: *const u8 = ::std::mem::transmute(&self.heaps.get_cpu_handle(id as usize));
-                        let sz = ::std::mem::size_of::<D3D12_CPU_DESCRIPTOR_HANDLE>();
-                        ::std::ptr::copy(handle, data[cur..].as_mut_ptr(), sz);
+    fn position(log: &[RecordedCommand], matches: &dyn Fn(&RecordedCommand) -> bool) -> usize {
+        log.iter().position(matches).expect("command was never recorded")
+    }
 
-                        sz
-                    } else {
-                        let handle: *const u8 = ::std::mem::transmute(&self.heaps.get_gpu_handle(id as u64));
-                        let sz = ::std::mem::size_of::<D3D12_GPU_DESCRIPTOR_HANDLE>();
-                        ::std::ptr::copy(handle, data[cur..].as_mut_ptr(), sz);
+    // a G-buffer pass with a depth buffer nothing reads afterwards, a scratch
+    // target overwritten in full and a composite into the back buffer
+    fn deferred(fg: &mut FrameGraph<RecordingBackend>) {
+        let back_buffer = back_buffer(fg);
 
-                        sz
-                    };
+        let (albedo, _) = fg.add_pass("GBuffer", |builder| {
+            let albedo = builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.5, 0.0, 0.0, 1.0]));
+            let depth = builder.create_depth("Depth", depth());
 
-                    cur += sz;
-                }
-            }
+            (albedo, depth)
+        }, Box::new(|_, _| {}));
+
+        let scratch = fg.add_pass("Scratch", |builder| {
+            builder.read_srv(&albedo);
+            builder.create_render_target("Scratch", target(InitialResourceState::DontCare, [0.0; 4]))
+        }, Box::new(|_, _| {}));
+
+        fg.add_pass("Composite", |builder| {
+            builder.read_srv(&scratch);
+            builder.write_render_target(back_buffer)
+        }, Box::new(|_, _| {}));
+    }
+
+    #[test]
+    fn render_pass_load_store_ops() {
+        let mut fg = graph();
+        fg.set_render_passes(true);
+
+        deferred(&mut fg);
+        fg.compile().unwrap();
+
+        // cleared on first use, depth isn't looked at again
+        assert_eq!(attachments(&fg, 0), vec![
+            (LoadOp::Clear(ClearValue::Color([0.5, 0.0, 0.0, 1.0])), StoreOp::Preserve),
+            (LoadOp::Clear(ClearValue::DepthStencil(1.0, 0)), StoreOp::Discard)
+        ]);
+        assert_eq!(attachments(&fg, 1), vec![(LoadOp::Discard, StoreOp::Preserve)]);
+        // imported resources keep what the previous owner left and hand
+        // their contents back
+        assert_eq!(attachments(&fg, 2), vec![(LoadOp::Preserve, StoreOp::Preserve)]);
+    }
+
+    #[test]
+    fn render_passes_off_by_default() {
+        let mut fg = graph();
 
-            for transition in trans {
-                let res = self.heaps.get_placed_resource_ptr(transition.resource as usize);
+        deferred(&mut fg);
+        fg.compile().unwrap();
 
-                let mut barrier: D3D12_RESOURCE_BARRIER = ResourceBarrier::Transition(
-                    res,
-                    transition.from.into_resource_state(),
-                    transition.to.into_resource_state()
-                ).into();
+        assert!((0..3).all(|pass| fg.render_pass_ops(pass).is_none()));
+    }
+
+    #[test]
+    fn execute_pass_follows_clears_and_render_pass() {
+        for &render_passes in &[false, true] {
+            let mut fg = graph();
+            fg.set_render_passes(render_passes);
+
+            deferred(&mut fg);
+            fg.compile().unwrap();
+            fg.backend_mut().clear();
+
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
 
-                barriers.push(barrier);
+            let log = fg.backend().log();
+            let execute = position(&log, &|command| match *command {
+                RecordedCommand::ExecutePass { ref name, .. } => name == "GBuffer",
+                _ => false
+            });
 
+            if render_passes {
+                let begin = position(&log, &|command| matches!(*command, RecordedCommand::BeginRenderPass { .. }));
+
+                assert!(begin < execute);
+                assert!(!log.iter().any(|command| matches!(*command, RecordedCommand::ClearRenderTarget { .. } | RecordedCommand::ClearDepthStencil { .. })));
+            } else {
+                let clear = position(&log, &|command| matches!(*command, RecordedCommand::ClearRenderTarget { .. }));
+                let clear_depth = position(&log, &|command| matches!(*command, RecordedCommand::ClearDepthStencil { .. }));
+
+                assert!(clear < execute && clear_depth < execute);
             }
+        }
+    }
+
+    fn count(fg: &FrameGraph<RecordingBackend>, matches: &dyn Fn(&RecordedCommand) -> bool) -> usize {
+        fg.backend().log().iter().filter(|command| matches(command)).count()
+    }
+
+    fn placed(command: &RecordedCommand) -> bool {
+        matches!(*command, RecordedCommand::CreatePlacedResource { .. })
+    }
+
+    fn released(command: &RecordedCommand) -> bool {
+        matches!(*command, RecordedCommand::ReleaseResource { .. })
+    }
+
+    #[test]
+    fn resize_releases_placed_resources() {
+        let mut fg = graph();
+        let mut live = 0;
 
-            for barrier in &mut barriers {
-                unsafe {
-                    if barrier.Type == D3D12_RESOURCE_BARRIER_TYPE_TRANSITION {
-                        let barrier = barrier.u.Transition_mut();
-                        //println!("Transition({:?}): {:?} => {:?}", barrier.pResource, barrier.StateBefore, barrier.StateAfter);
-                    } else if barrier.Type == D3D12_RESOURCE_BARRIER_TYPE_ALIASING {
-                        let barrier = barrier.u.Aliasing_mut();
-                        //println!("Alias({:?} => {:?})", barrier.pResourceBefore, barrier.pResourceAfter);
+        for &(width, height) in &[(64, 64), (128, 96), (32, 32)] {
+            fg.set_resolution(width, height);
+
+            let before = count(&fg, &placed);
+            deferred(&mut fg);
+            fg.compile().unwrap();
+            fg.finish();
+
+            // everything placed for the previous resolution is gone
+            assert_eq!(count(&fg, &released), before);
+            live = count(&fg, &placed) - before;
+            assert!(live > 0);
+        }
+
+        assert_eq!(count(&fg, &placed) - count(&fg, &released), live);
+    }
+
+    // replays the log, every transition has to start from the state the
+    // resource was actually left in, placed resources start out in the one
+    // they were created in
+    fn check_states(log: &[RecordedCommand]) {
+        use std::collections::HashMap;
+
+        let mut states: HashMap<u32, Vec<ResourceStates>> = HashMap::new();
+
+        for command in log {
+            match *command {
+                RecordedCommand::CreatePlacedResource { resource, ref desc, state, .. } => {
+                    let count = match desc.dimension {
+                        ResourceDimension::Buffer => 1,
+                        _ => desc.mip_levels.max(1) as usize * desc.depth_or_array_size.max(1) as usize
+                    };
+
+                    states.insert(resource, vec![state; count]);
+                },
+                RecordedCommand::Barrier { barrier: ResourceBarrier::Transition(resource, subresource, from, to, flags), .. } => {
+                    // the begin half already moved the resource along
+                    if flags.contains(BarrierFlags::END_ONLY) {
+                        continue;
                     }
-                };
+
+                    // imported resources aren't tracked
+                    let current = match states.get_mut(&resource) {
+                        Some(current) => current,
+                        None => continue
+                    };
+
+                    let range = if subresource == ALL_SUBRESOURCES { 0..current.len() } else { subresource as usize..subresource as usize + 1 };
+                    for sub in range {
+                        assert_eq!(current[sub], from, "resource {} subresource {} isn't in the state the barrier starts from", resource, sub);
+                        current[sub] = to;
+                    }
+                },
+                _ => {}
             }
+        }
+    }
+
+    #[test]
+    fn states_carry_over_cached_frames() {
+        let mut fg = graph();
 
-            unsafe { (*list).ResourceBarrier(barriers.len() as u32, barriers.as_ptr()); }
+        for frame in 0..3 {
+            deferred(&mut fg);
+            let report = fg.compile().unwrap();
 
-            (pass.exec)(list, unsafe { ::std::mem::transmute(data.as_ptr()) })
+            // nothing but the declarations decides whether the graph is reused
+            assert_eq!(report.cached, frame > 0);
+
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+            fg.finish();
         }
+
+        check_states(&fg.backend().log());
+
+        // albedo and scratch are left readable, and written first thing the
+        // next frame
+        let carried = count(&fg, &|command| match *command {
+            RecordedCommand::Barrier { barrier: ResourceBarrier::Transition(_, _, from, to, _), .. } => from.contains(ResourceStates::PIXEL_SHADER_RESOURCE) && to == ResourceStates::RENDER_TARGET,
+            _ => false
+        });
+        assert_eq!(carried, 4);
     }
 
-    pub fn finish(&mut self) {
-        self.renderpasses.clear();
-        self.renderpass_transitions.clear();
-        self.resources.clear();
-        self.views.clear();
-        self.virtual_offset = 0;
-        self.virtual_view = 0;
+    // a blur between the G-buffer and the composite that can be toggled, the
+    // composite samples the albedo directly without it
+    fn toggled(fg: &mut FrameGraph<RecordingBackend>, blur: bool) {
+        let back_buffer = back_buffer(fg);
+
+        let albedo = fg.add_pass("GBuffer", |builder| builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+
+        let blurred = fg.add_pass_if("Blur", blur, |builder| {
+            builder.read_srv(&albedo);
+            let blurred = builder.create_render_target("Blurred", target(InitialResourceState::DontCare, [0.0; 4]));
+            builder.passthrough(&albedo, &blurred);
+
+            blurred
+        }, Box::new(|_, _| {}));
+
+        fg.add_pass("Composite", |builder| {
+            builder.read_srv(&blurred);
+            builder.write_render_target(back_buffer)
+        }, Box::new(|_, _| {}));
     }
-}
 
+    #[test]
+    fn toggled_pass_barriers_start_from_actual_states() {
+        let mut fg = graph();
 
-// created: Vec<(&'static str, u32, TransitionFlags, D3D12_RESOURCE_DESC)>,
-//#[derive(Debug)]
-pub struct PlacedResource {
-    name: &'static str,
-    resource_id: u32,
-    flags: TransitionFlags,
-    desc: D3D12_RESOURCE_DESC,
-}
+        let frame = |fg: &mut FrameGraph<RecordingBackend>, blur: bool| {
+            toggled(fg, blur);
+            fg.compile().unwrap();
 
-#[derive(Clone)]
-pub enum ResourceViewDesc {
-    RenderTarget(D3D12_RENDER_TARGET_VIEW_DESC),
-    ShaderResource(D3D12_SHADER_RESOURCE_VIEW_DESC),
-}
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+            fg.finish();
+        };
 
-//#[derive(Debug)]
-#[derive(Clone)]
-pub struct ResourceView {
-    pub resource_id: u32,
-    pub view_id: u32,
-    pub desc: ResourceViewDesc
-}
+        for &blur in &[true, false, true, false] {
+            frame(&mut fg, blur);
+        }
 
-//#[derive(Debug)]
-pub struct FrameGraphBuilder {
-    device: *mut ID3D12Device,
-    created: Vec<PlacedResource>,
-    resources: Vec<(u32, TransitionFlags)>,
-    views: Vec<ResourceView>,
-    counter: u32,
-    view_counter: u32,
-}
+        assert_eq!(fg.cache_stats(), CacheStats { hits: 2, misses: 2 });
 
-impl FrameGraphBuilder {
-    fn new(device: *mut ID3D12Device, offset: u32, view_offset: u32) -> Self {
-        FrameGraphBuilder {
-            device: device,
-            created: Vec::new(),
-            resources: Vec::new(),
-            views: Vec::new(),
-            counter: offset,
-            view_counter: view_offset
+        // every layout is placed anew, in the state its first use expects
+        fg.set_resolution(128, 128);
+        for &blur in &[true, false, true, true] {
+            frame(&mut fg, blur);
         }
+
+        assert_eq!(fg.cache_stats(), CacheStats { hits: 4, misses: 4 });
+        check_states(&fg.backend().log());
     }
 
-    pub fn create_render_target(&mut self, name: &'static str, desc: RenderTargetDesc) -> RenderTargetResource {
-        let virtual_id = self.counter;
-        self.counter += 1;
-        let res = FrameGraphResource {
-            name: name,
-            view_id: self.view_counter,
-            resource_id: virtual_id
-        };
+    #[test]
+    fn cached_layouts_point_views_at_their_own_resources() {
+        let mut fg = graph();
 
-        let resource_desc = D3D12_RESOURCE_DESC {
-            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-            Alignment: 0,
-            Width: 1280,
-            Height: 720,
-            DepthOrArraySize: 1,
-            MipLevels: desc.mip_levels as u16,
-            Format: desc.format.into(),
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 1
-            },
-            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
-            Flags: D3D12_RESOURCE_FLAG_NONE,
+        let mut start = 0;
+        // the back buffer is imported, the rest is placed in the current layout
+        let mut resources = vec![1000];
+        for &blur in &[true, false, true] {
+            start = fg.backend().log().len();
+
+            toggled(&mut fg, blur);
+            fg.compile().unwrap();
+
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+
+            resources.truncate(1);
+            resources.extend((0..fg.resources.len()).filter_map(|idx| fg.heaps.get_placed_resource_ptr(idx)));
+            fg.finish();
+        }
+
+        let log = fg.backend().log();
+        let views = log[start..].iter().filter_map(|command| match *command {
+            RecordedCommand::CreateView { resource, .. } => Some(resource),
+            _ => None
+        }).collect::<Vec<_>>();
+
+        assert!(views.len() > 1);
+        assert!(views.iter().all(|resource| resources.contains(resource)));
+    }
+
+    #[test]
+    fn compiled_cache_keeps_recent_structures() {
+        let mut fg = graph();
+
+        let compile = |fg: &mut FrameGraph<RecordingBackend>, structure: usize| {
+            let back_buffer = back_buffer(fg);
+            fg.add_pass(format!("Composite {}", structure), |builder| builder.write_render_target(back_buffer), Box::new(|_, _| {}));
+
+            let report = fg.compile().unwrap();
+            fg.finish();
+
+            report.cached
         };
 
-        let mut view_desc: D3D12_RENDER_TARGET_VIEW_DESC = unsafe { ::std::mem::zeroed() };
-        view_desc.Format = desc.format.into();
-        view_desc.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2D;
-        unsafe {
-            (*view_desc.u.Texture2D_mut()) = D3D12_TEX2D_RTV {
-                MipSlice: 0,
-                PlaneSlice: 0
-            };
+        for structure in 0..COMPILED_CACHE_SIZE + 1 {
+            assert!(!compile(&mut fg, structure));
         }
 
-        self.views.push(ResourceView {
-            resource_id: virtual_id,
-            view_id: self.view_counter,
-            desc: ResourceViewDesc::RenderTarget(view_desc)
-        });
+        // the oldest structure made room for the last one
+        assert!(compile(&mut fg, 1));
+        assert!(!compile(&mut fg, 0));
+        assert_eq!(fg.cache_stats(), CacheStats { hits: 1, misses: COMPILED_CACHE_SIZE as u64 + 2 });
+    }
 
-        self.view_counter += 1;
+    #[test]
+    fn imported_target_cleared_on_first_use() {
+        for &render_passes in &[false, true] {
+            let mut fg = graph();
+            fg.set_render_passes(render_passes);
 
-        self.created.push(PlacedResource {
-            resource_id: virtual_id,
-            flags: TransitionFlags::RENDER_TARGET,
-            desc: resource_desc,
-            name: name
-        });
-        self.write(res, TransitionFlags::RENDER_TARGET);
+            let back_buffer = back_buffer(&mut fg);
+            fg.add_pass("Composite", |builder| builder.clear_render_target(back_buffer, [0.1, 0.2, 0.3, 1.0]), Box::new(|_, _| {}));
+            fg.compile().unwrap();
 
-        RenderTargetResource(res)
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+
+            let cleared = count(&fg, &|command| match *command {
+                RecordedCommand::ClearRenderTarget { color, .. } => color == [0.1, 0.2, 0.3, 1.0],
+                _ => false
+            });
+
+            if render_passes {
+                assert_eq!(cleared, 0);
+                assert_eq!(attachments(&fg, 0), vec![(LoadOp::Clear(ClearValue::Color([0.1, 0.2, 0.3, 1.0])), StoreOp::Preserve)]);
+            } else {
+                assert_eq!(cleared, 1);
+            }
+        }
     }
 
-    pub fn create_depth(&mut self, name: &'static str, desc: DepthDesc) -> DepthWriteResource {
-        let virtual_id = self.counter;
-        self.counter += 1;
-        let res = FrameGraphResource {
-            name: name,
-            view_id: self.view_counter,
-            resource_id: virtual_id
+    #[test]
+    fn history_requested_twice() {
+        let mut fg = graph();
+
+        fg.add_pass("TAA", |builder| builder.create_history("Accumulation", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        fg.add_pass("Motion", |builder| builder.create_history("Accumulation", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::HistoryRequestedTwice {
+            pass: "Motion".to_string(),
+            history: "Accumulation".to_string()
+        }));
+    }
+
+    #[test]
+    fn passthrough_format_mismatch() {
+        let mut fg = graph();
+
+        let depth = fg.add_pass("Prepass", |builder| builder.create_depth("Depth", depth()), Box::new(|_, _| {}));
+
+        fg.add_pass("Forward", |builder| {
+            let albedo = builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4]));
+            builder.passthrough(&depth, &albedo);
+        }, Box::new(|_, _| {}));
+
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::PassthroughMismatch {
+            pass: "Forward".to_string(),
+            from: "Depth".to_string(),
+            to: "Albedo".to_string()
+        }));
+    }
+
+    #[derive(ResourceBinding)]
+    struct GBuffer {
+        albedo: RenderTargetResource,
+        depth: DepthWriteResource
+    }
+
+    #[test]
+    fn derived_binding_resolves_every_field() {
+        use std::sync::Mutex;
+
+        let mut fg = graph();
+        let resolved = Arc::new(Mutex::new(Vec::new()));
+
+        let gbuffer = {
+            let resolved = resolved.clone();
+
+            fg.add_pass("GBuffer", |builder| {
+                builder.set_side_effects(true);
+
+                GBuffer {
+                    albedo: builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4])),
+                    depth: builder.create_depth("Depth", depth())
+                }
+            }, Box::new(move |_, physical: &GBufferPhysical| {
+                resolved.lock().unwrap().extend(vec![physical.albedo, physical.depth]);
+            }))
         };
 
-        self.view_counter += 1;
+        fg.compile().unwrap();
+        let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+        fg.exec(list);
 
-        let resource_desc = D3D12_RESOURCE_DESC {
-            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-            Alignment: 0,
-            Width: 1280,
-            Height: 720,
-            DepthOrArraySize: 1,
-            MipLevels: 1,// desc.mip_levels as u16,
-            Format: desc.format.into(),
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 1
+        // the recording backend hands out the view ids as handles
+        assert_eq!(*resolved.lock().unwrap(), vec![
+            CpuDescriptorHandle { ptr: gbuffer.albedo.0.view_id as usize },
+            CpuDescriptorHandle { ptr: gbuffer.depth.0.view_id as usize }
+        ]);
+    }
+
+    // (resource, heap, offset, size) of the last placed resource named `name`
+    fn placement(fg: &FrameGraph<RecordingBackend>, name: &str) -> (u32, u32, u64, u64) {
+        let prefix = format!("{} (", name);
+
+        fg.backend().log().iter().filter_map(|command| match *command {
+            RecordedCommand::CreatePlacedResource { resource, heap, offset, ref desc, name: ref placed, .. } if placed.starts_with(&prefix) => {
+                Some((resource, heap, offset, fg.backend().resource_allocation_info(desc).0))
             },
-            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
-            Flags: D3D12_RESOURCE_FLAG_NONE,
-        };
+            _ => None
+        }).next_back().expect("resource was never placed")
+    }
 
-        self.created.push(PlacedResource {
-            resource_id: virtual_id,
-            flags: TransitionFlags::DEPTH_WRITE,
-            desc: resource_desc,
-            name: name
-        });
-        self.write(res, TransitionFlags::DEPTH_WRITE);
+    fn executed(name: &'static str) -> Box<dyn Fn(&RecordedCommand) -> bool> {
+        Box::new(move |command| match *command {
+            RecordedCommand::ExecutePass { name: ref executed, .. } => executed == name,
+            _ => false
+        })
+    }
 
-        DepthWriteResource(res)
+    #[test]
+    fn exec_parallel_records_every_pass_once_on_its_chunk() {
+        let mut fg = graph();
+
+        let mut lights = fg.add_pass("Pass 0", |builder| builder.create_uav("Lights", UnorderedAccessDesc {
+            format: TextureFormat::RGBA8,
+            size: TextureSize::Full,
+            mip_levels: 1,
+            array_size: 1
+        }), Box::new(|_, _| {}));
+        for pass in 1..5 {
+            lights = fg.add_pass(format!("Pass {}", pass), |builder| builder.read_write_uav(lights), Box::new(|_, _| {}));
+        }
+        fg.mark_output(&lights);
+
+        fg.compile().unwrap();
+        let lists = fg.exec_parallel(2);
+        assert_eq!(lists.len(), 2);
+
+        let log = fg.backend().log();
+        for pass in 0..5 {
+            let name = format!("Pass {}", pass);
+            let recorded = log.iter().filter_map(|command| match *command {
+                RecordedCommand::ExecutePass { list, name: ref executed } if *executed == name => Some(list),
+                _ => None
+            }).collect::<Vec<_>>();
+
+            // chunks of three passes
+            assert_eq!(recorded, vec![lists[pass / 3]]);
+        }
     }
 
-    pub fn read_srv<T: IntoTypedResource<ShaderResource>>(&mut self, resource: &T) -> ShaderResource {
-        ShaderResource(self.read(resource.get_virtual_resource(), TransitionFlags::SHADER_RESOURCE))
+    #[test]
+    fn consecutive_uav_writes_wait_on_each_other() {
+        let mut fg = graph();
+
+        let lights = fg.add_pass("ClearLights", |builder| builder.create_uav("Lights", UnorderedAccessDesc {
+            format: TextureFormat::RGBA8,
+            size: TextureSize::Full,
+            mip_levels: 1,
+            array_size: 1
+        }), Box::new(|_, _| {}));
+        let lights = fg.add_pass("CullLights", |builder| builder.read_write_uav(lights), Box::new(|_, _| {}));
+        fg.mark_output(&lights);
+
+        fg.compile().unwrap();
+        let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+        fg.exec(list);
+
+        let resource = placement(&fg, "Lights").0;
+        let log = fg.backend().log();
+        let wait = position(&log, &|command| match *command {
+            RecordedCommand::Barrier { barrier: ResourceBarrier::Uav(uav), .. } => uav == resource,
+            _ => false
+        });
+
+        assert!(position(&log, &*executed("ClearLights")) < wait && wait < position(&log, &*executed("CullLights")));
     }
 
-    pub fn read_depth<T: IntoTypedResource<DepthReadResource>>(&mut self, resource: &T) -> DepthReadResource {
-        DepthReadResource(self.read(resource.get_virtual_resource(), TransitionFlags::DEPTH_READ))
+    #[test]
+    fn aliasing_only_shares_memory_between_disjoint_lifetimes() {
+        let mut fg = graph();
+        let back_buffer = back_buffer(&mut fg);
+
+        // a chain where every target is read by the pass creating the next
+        let first = fg.add_pass("First", |builder| builder.create_render_target("First", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        let second = fg.add_pass("Second", |builder| {
+            builder.read_srv(&first);
+            builder.create_render_target("Second", target(InitialResourceState::Clear, [0.0; 4]))
+        }, Box::new(|_, _| {}));
+        let third = fg.add_pass("Third", |builder| {
+            builder.read_srv(&second);
+            builder.create_render_target("Third", target(InitialResourceState::Clear, [0.0; 4]))
+        }, Box::new(|_, _| {}));
+        fg.add_pass("Composite", |builder| {
+            builder.read_srv(&third);
+            builder.write_render_target(back_buffer)
+        }, Box::new(|_, _| {}));
+
+        fg.compile().unwrap();
+        let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+        fg.exec(list);
+
+        let overlap = |a: (u32, u32, u64, u64), b: (u32, u32, u64, u64)| a.1 == b.1 && a.2 < b.2 + b.3 && b.2 < a.2 + a.3;
+        let (first, second, third) = (placement(&fg, "First"), placement(&fg, "Second"), placement(&fg, "Third"));
+
+        assert!(!overlap(first, second));
+        assert!(!overlap(second, third));
+        assert!(overlap(first, third));
+
+        // the third target takes the memory over once the first is done
+        let log = fg.backend().log();
+        let alias = position(&log, &|command| match *command {
+            RecordedCommand::Barrier { barrier: ResourceBarrier::Alias(None, resource), .. } => resource == third.0,
+            _ => false
+        });
+
+        assert!(position(&log, &*executed("Second")) < alias);
+        assert!(alias < position(&log, &*executed("Third")));
     }
 
-    pub fn write_depth<T: IntoTypedResource<DepthWriteResource>>(&mut self, resource: T) -> DepthWriteResource {
-        DepthWriteResource(self.write(resource.get_virtual_resource(), TransitionFlags::DEPTH_WRITE))
+    #[test]
+    fn exec_queues_waits_across_queues_and_frames() {
+        let mut fg = graph();
+
+        for _ in 0..2 {
+            let back_buffer = back_buffer(&mut fg);
+            let depth = fg.add_pass("Shadows", |builder| builder.create_depth("Depth", depth()), Box::new(|_, _| {}));
+            let lights = fg.add_pass("LightCulling", |builder| {
+                builder.set_queue(QueueType::Compute);
+                builder.read_srv(&depth);
+                builder.create_uav("Lights", UnorderedAccessDesc {
+                    format: TextureFormat::RGBA8,
+                    size: TextureSize::Full,
+                    mip_levels: 1,
+                    array_size: 1
+                })
+            }, Box::new(|_, _| {}));
+            fg.add_pass("Shading", |builder| {
+                builder.read_srv(&lights);
+                builder.write_render_target(back_buffer)
+            }, Box::new(|_, _| {}));
+
+            fg.compile().unwrap();
+            fg.backend_mut().clear();
+            fg.exec_queues();
+            check_states(&fg.backend().log());
+            fg.finish();
+        }
+
+        let submits = fg.backend().log().iter().filter_map(|command| match *command {
+            RecordedCommand::SubmitCommands { queue, ref waits, signal, .. } => Some((queue, waits.clone(), signal)),
+            _ => None
+        }).collect::<Vec<_>>();
+
+        // the second frame's values continue from the first, and its first
+        // compute batch waits for the first frame's graphics work
+        assert_eq!(submits, vec![
+            (QueueType::Graphics, vec![(QueueType::Compute, 1)], Some(3)),
+            (QueueType::Compute, vec![(QueueType::Graphics, 3)], Some(2)),
+            (QueueType::Graphics, vec![(QueueType::Compute, 2)], Some(4))
+        ]);
     }
 
-    fn read(&mut self, resource: FrameGraphResource, transition: TransitionFlags) -> FrameGraphResource {
-        self.resources.push((resource.resource_id, transition));
-        resource
+    #[test]
+    fn array_slices_transition_on_their_own() {
+        let mut fg = graph();
+        let back_buffer = back_buffer(&mut fg);
+
+        let cascades = fg.add_pass("Cascade0", |builder| builder.create_uav("Cascades", UnorderedAccessDesc {
+            format: TextureFormat::RGBA8,
+            size: TextureSize::Full,
+            mip_levels: 1,
+            array_size: 2
+        }), Box::new(|_, _| {}));
+        let cascades = fg.add_pass("Cascade1", |builder| {
+            builder.read_srv_range(&cascades, SubresourceRange::slice(0));
+            builder.read_write_uav_range(cascades, SubresourceRange::slice(1))
+        }, Box::new(|_, _| {}));
+        fg.add_pass("Composite", |builder| {
+            builder.read_srv(&cascades);
+            builder.write_render_target(back_buffer)
+        }, Box::new(|_, _| {}));
+
+        fg.compile().unwrap();
+
+        let resource = cascades.0.resource_id;
+        let shader_resource = ResourceStates::NON_PIXEL_SHADER_RESOURCE | ResourceStates::PIXEL_SHADER_RESOURCE;
+        // leaving out the uav barrier of the second pass's write
+        let transitions = |pass| fg.transitions(pass).iter()
+            .filter(|transition| transition.resource == resource && transition.from != transition.to)
+            .map(|transition| (transition.subresource, transition.from, transition.to))
+            .collect::<Vec<_>>();
+
+        // the second slice is still written while the first one is read
+        assert_eq!(transitions(1), vec![(0, ResourceStates::UNORDERED_ACCESS, shader_resource)]);
+        assert_eq!(transitions(2), vec![(1, ResourceStates::UNORDERED_ACCESS, shader_resource)]);
+
+        let slices = fg.renderpasses[1].views.iter().map(|view| match view.desc {
+            ResourceViewDesc::UnorderedAccess { first_array_slice, array_size, .. } |
+            ResourceViewDesc::ShaderResource { first_array_slice, array_size, .. } => (first_array_slice, array_size),
+            _ => panic!("unexpected view")
+        }).collect::<Vec<_>>();
+        assert_eq!(slices, vec![(0, 1), (1, 1)]);
     }
 
-    fn write(&mut self, resource: FrameGraphResource, transition: TransitionFlags) -> FrameGraphResource {
-        self.resources.push((resource.resource_id, transition));
-        resource
+    #[test]
+    fn split_barriers_begin_after_last_use() {
+        for &split in &[true, false] {
+            let mut fg = graph();
+            fg.set_split_barriers(split);
+
+            let back_buffer = back_buffer(&mut fg);
+            let albedo = fg.add_pass("GBuffer", |builder| builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+            fg.add_pass("Unrelated", |builder| {
+                builder.set_side_effects(true);
+                builder.create_render_target("Unrelated", target(InitialResourceState::Clear, [0.0; 4]))
+            }, Box::new(|_, _| {}));
+            fg.add_pass("Composite", |builder| {
+                builder.read_srv(&albedo);
+                builder.write_render_target(back_buffer)
+            }, Box::new(|_, _| {}));
+
+            fg.compile().unwrap();
+
+            let transition = *fg.transitions(2).iter().find(|transition| transition.resource == albedo.0.resource_id).unwrap();
+            assert_eq!(transition.begin, if split { Some(0) } else { None });
+
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+
+            let log = fg.backend().log();
+            let half = |flags: BarrierFlags| log.iter().position(|command| match *command {
+                RecordedCommand::Barrier { barrier: ResourceBarrier::Transition(_, _, _, _, barrier_flags), .. } => barrier_flags == flags,
+                _ => false
+            });
+
+            if split {
+                let (begin, end) = (half(BarrierFlags::BEGIN_ONLY).unwrap(), half(BarrierFlags::END_ONLY).unwrap());
+
+                assert!(position(&log, &*executed("GBuffer")) < begin && begin < position(&log, &*executed("Unrelated")));
+                assert!(position(&log, &*executed("Unrelated")) < end && end < position(&log, &*executed("Composite")));
+            } else {
+                assert!(half(BarrierFlags::BEGIN_ONLY).is_none() && half(BarrierFlags::END_ONLY).is_none());
+            }
+
+            check_states(&log);
+        }
     }
-}
 
-#[derive(Debug, Copy, Clone)]
-pub enum TextureSize {
-    Full,
-    Half,
-    Explicit(u32, u32)
-}
+    #[test]
+    fn passes_nothing_reads_are_culled() {
+        let mut fg = graph();
+        let back_buffer = back_buffer(&mut fg);
 
-#[derive(Debug, Copy, Clone)]
-pub enum InitialResourceState {
-    Clear,
-    DontCare
-}
+        fg.add_pass("Unused", |builder| builder.create_render_target("Unused", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        fg.add_pass("Nothing", |_| {}, Box::new(|_, _| {}));
+        fg.add_pass("Composite", |builder| builder.write_render_target(back_buffer), Box::new(|_, _| {}));
 
-#[derive(Debug, Copy, Clone)]
-pub enum ResourceState {
-    Clear,
-    DontCare
-}
+        let report = fg.compile().unwrap();
+        let culled = report.culled.iter().map(|pass| (&pass.name[..], pass.index, pass.reason.clone())).collect::<Vec<_>>();
 
-#[derive(Debug, Copy, Clone)]
-pub enum DepthFormat {
-    D32,
-    D24
-}
+        assert_eq!(culled, vec![
+            ("Unused", 0, CullReason::UnusedOutputs(vec!["Unused".to_string()])),
+            ("Nothing", 1, CullReason::NoOutputs)
+        ]);
 
-#[derive(Debug, Copy, Clone)]
-pub struct DepthDesc {
-    pub format: DepthFormat,
-    pub size: TextureSize,
-    pub state: InitialResourceState,
-}
+        let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+        fg.exec(list);
 
-#[derive(Debug, Copy, Clone)]
-pub enum TextureFormat {
-    RGBA8,
-    R8,
-}
+        assert_eq!(count(&fg, &placed), 0);
+        assert_eq!(count(&fg, &|command| matches!(*command, RecordedCommand::ExecutePass { .. })), 1);
 
-impl From<DepthFormat> for DXGI_FORMAT {
-    fn from(f: DepthFormat) -> DXGI_FORMAT {
+        // culling everything leaves nothing to run
+        fg.finish();
+        fg.add_pass("Unused", |builder| builder.create_render_target("Unused", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::EmptyGraph));
+    }
 
-        match f {
-            _ => DXGI_FORMAT_D32_FLOAT
-            // DepthFormat::D32 => DXGI_FORMAT_D32_FLOAT,
-            // DepthFormat::D24 => DXGI_FORMAT_D24_FLOAT,
-        }
+    #[test]
+    fn invalid_graphs_fail_to_compile() {
+        let mut fg = graph();
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::EmptyGraph));
+
+        // a handle of the previous frame
+        let albedo = fg.add_pass("GBuffer", |builder| {
+            builder.set_side_effects(true);
+            builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4]))
+        }, Box::new(|_, _| {}));
+        fg.compile().unwrap();
+        fg.finish();
+
+        fg.add_pass("Composite", |builder| {
+            builder.set_side_effects(true);
+            builder.read_srv(&albedo);
+        }, Box::new(|_, _| {}));
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::StaleHandle {
+            pass: "Composite".to_string(),
+            resource: "Albedo".to_string()
+        }));
+        fg.finish();
+
+        // sampled and written by the same pass
+        let albedo = fg.add_pass("GBuffer", |builder| builder.create_render_target("Albedo", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        fg.add_pass("Blur", |builder| {
+            builder.set_side_effects(true);
+            builder.read_srv(&albedo);
+            builder.read_write_uav(albedo);
+        }, Box::new(|_, _| {}));
+        assert_eq!(fg.compile().err(), Some(FrameGraphError::ConflictingStates {
+            pass: "Blur".to_string(),
+            resource: "Albedo".to_string()
+        }));
     }
-}
 
-impl From<TextureFormat> for DXGI_FORMAT {
-    fn from(f: TextureFormat) -> DXGI_FORMAT {
+    #[test]
+    fn dot_greys_out_culled_passes() {
+        let mut fg = graph();
+        let back_buffer = back_buffer(&mut fg);
 
-        match f {
-            TextureFormat::RGBA8 => DXGI_FORMAT_R8G8B8A8_UNORM,
-            TextureFormat::R8 => DXGI_FORMAT_R8_UNORM
-        }
+        fg.add_pass("Unused", |builder| builder.create_render_target("Unused", target(InitialResourceState::Clear, [0.0; 4])), Box::new(|_, _| {}));
+        fg.add_pass("Composite", |builder| builder.write_render_target(back_buffer), Box::new(|_, _| {}));
+        fg.compile().unwrap();
+
+        // culled passes are still part of the picture
+        let dot = fg.to_dot();
+        assert!(dot.lines().any(|line| line.contains("label=\"Unused\", shape=box") && line.contains("fillcolor=lightgrey")));
+        assert!(dot.lines().any(|line| line.contains("label=\"Composite\", shape=box") && line.contains("fillcolor=lightblue")));
     }
-}
 
-#[derive(Debug, Copy, Clone)]
-pub struct RenderTargetDesc {
-    pub format: TextureFormat,
-    pub size: TextureSize,
-    pub mip_levels: u32,
-    pub state: InitialResourceState,
+    // resource the last view with `view_id` was created on
+    fn viewed(fg: &FrameGraph<RecordingBackend>, view_id: u32) -> u32 {
+        fg.backend().log().iter().filter_map(|command| match *command {
+            RecordedCommand::CreateView { resource, view_id: view, .. } if view == view_id => Some(resource),
+            _ => None
+        }).next_back().expect("view was never created")
+    }
+
+    #[test]
+    fn history_swaps_halves_and_clears_fresh_ones() {
+        let mut fg = graph();
+        // (written, read) resource and clears of every frame
+        let mut frames = Vec::new();
+
+        for frame in 0..3 {
+            // sized anew, so fresh again
+            if frame == 2 {
+                fg.set_resolution(128, 128);
+            }
+
+            let back_buffer = back_buffer(&mut fg);
+            let (current, previous) = fg.add_pass("TAA", |builder| builder.create_history("Accumulation", target(InitialResourceState::DontCare, [0.25, 0.0, 0.0, 1.0])), Box::new(|_, _| {}));
+            fg.add_pass("Composite", |builder| {
+                builder.read_srv(&current);
+                builder.write_render_target(back_buffer)
+            }, Box::new(|_, _| {}));
+
+            fg.compile().unwrap();
+            let halves = (viewed(&fg, current.0.view_id), viewed(&fg, previous.0.view_id));
+
+            fg.backend_mut().clear();
+            let list = fg.backend_mut().begin_commands(QueueType::Graphics);
+            fg.exec(list);
+            check_states(&fg.backend().log());
+
+            let clears = count(&fg, &|command| match *command {
+                RecordedCommand::ClearRenderTarget { color, .. } => color == [0.25, 0.0, 0.0, 1.0],
+                _ => false
+            });
+
+            frames.push((halves, clears));
+            fg.finish();
+        }
+
+        let ((written, read), _) = frames[0];
+        assert_ne!(written, read);
+        // what was written is read the next frame
+        assert_eq!(frames[1].0, (read, written));
+        assert!(frames[2].0 .0 != written && frames[2].0 .0 != read);
+
+        assert_eq!(frames.iter().map(|&(_, clears)| clears).collect::<Vec<_>>(), vec![1, 0, 1]);
+    }
 }
@@ -1,51 +1,95 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+// winapi's macros are only used by the d3d12 backend
+#[cfg_attr(windows, macro_use)]
 extern crate winapi;
 extern crate term;
 #[macro_use]
 extern crate derivative;
 extern crate svg;
+// only the tests derive bindings so far
+#[cfg_attr(test, macro_use)]
+extern crate atsssvt_derive;
+
+// `#[derive(ResourceBinding)]` names the frame graph `::atsssvt::framegraph`
+extern crate self as atsssvt;
 
 #[macro_use]
 extern crate bitflags;
 
+#[cfg(windows)]
 use winapi::um::d3d12::*;
+#[cfg(windows)]
 use winapi::um::d3d12sdklayers::*;
+#[cfg(windows)]
 use winapi::um::d3dcommon::*;
 
+#[cfg(windows)]
 use winapi::shared::winerror::*;
+#[cfg(windows)]
 use winapi::shared::dxgiformat::*;
+#[cfg(windows)]
 use winapi::shared::dxgitype::*;
+#[cfg(windows)]
 use winapi::shared::dxgi::*;
+#[cfg(windows)]
 use winapi::shared::dxgi1_2::*;
+#[cfg(windows)]
 use winapi::shared::dxgi1_3::*;
+#[cfg(windows)]
 use winapi::shared::dxgi1_4::*;
 
+#[cfg(windows)]
 use winapi::shared::minwindef::*;
+#[cfg(windows)]
 use winapi::shared::windef::*;
 
+#[cfg(windows)]
 use winapi::um::libloaderapi::*;
+#[cfg(windows)]
 use winapi::um::synchapi::*;
+#[cfg(windows)]
 use winapi::um::winbase::*;
+#[cfg(windows)]
 use winapi::um::winuser::*;
+#[cfg(windows)]
 use winapi::um::winnt::*;
 
+#[cfg(windows)]
 use winapi::Interface;
 
 mod alloc;
+mod backend;
+#[cfg(windows)]
+mod d3d12;
 mod framegraph;
+#[cfg(windows)]
 mod pipeline;
-
+mod profiler;
+mod recording;
+mod schedule;
+
+#[cfg(windows)]
+use backend::*;
+#[cfg(windows)]
+use d3d12::*;
+#[cfg(windows)]
 use framegraph::*;
+#[cfg(windows)]
 use pipeline::*;
 
+#[cfg(windows)]
 use std::ffi::OsStr;
+#[cfg(windows)]
 use std::os::windows::ffi::OsStrExt;
 
+#[cfg(windows)]
 use std::mem;
+#[cfg(windows)]
 use std::ptr;
 
+#[cfg(windows)]
 unsafe extern "system" fn callback(window: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     if msg == WM_DESTROY {
         PostQuitMessage(0);
@@ -55,6 +99,7 @@ unsafe extern "system" fn callback(window: HWND, msg: UINT, wparam: WPARAM, lpar
     DefWindowProcW(window, msg, wparam, lparam)
 }
 
+#[cfg(windows)]
 unsafe fn register_window_class() -> Vec<u16> {
     let class_name: Vec<u16> = OsStr::new("Window Class").encode_wide().chain(Some(0).into_iter()).collect::<Vec<u16>>();
 
@@ -77,7 +122,8 @@ unsafe fn register_window_class() -> Vec<u16> {
     class_name
 }
 
-unsafe fn create_window(factory: *mut IDXGIFactory4, queue: *mut ID3D12CommandQueue) -> (HWND, *mut IDXGISwapChain1) {
+#[cfg(windows)]
+unsafe fn create_window(factory: *mut IDXGIFactory4, queue: *mut ID3D12CommandQueue) -> (HWND, *mut IDXGISwapChain3) {
     let class_name = register_window_class();
 
     let title: Vec<u16> = OsStr::new("D3D12 [FG]").encode_wide().chain(Some(0).into_iter()).collect::<Vec<u16>>();
@@ -119,9 +165,20 @@ unsafe fn create_window(factory: *mut IDXGIFactory4, queue: *mut ID3D12CommandQu
         &mut swapchain as *mut *mut _ as *mut *mut _
      );
 
-    (hwnd, swapchain)
+    // needed for `GetCurrentBackBufferIndex`
+    let mut swapchain3: *mut IDXGISwapChain3 = ptr::null_mut();
+    (*swapchain).QueryInterface(&IDXGISwapChain3::uuidof(), &mut swapchain3 as *mut *mut _ as *mut *mut _);
+    (*swapchain).Release();
+
+    (hwnd, swapchain3)
+}
+
+#[cfg(not(windows))]
+fn main() {
+    println!("The D3D12 demo only runs on Windows");
 }
 
+#[cfg(windows)]
 fn main() {
     let (device, queue, hwnd, swapchain) = {
         unsafe { enable_debug_layer(); }
@@ -153,26 +210,6 @@ fn main() {
 
     println!("{:?}, {:?}, {:?}, {:?}", device, queue, hwnd, swapchain);
 
-    let (allocator, list) = unsafe {
-        let mut allocator: *mut ID3D12CommandAllocator = ptr::null_mut();
-        (*device.device).CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT, &ID3D12CommandAllocator::uuidof(), &mut allocator as *mut *mut _ as *mut *mut _);
-
-        let mut list: *mut ID3D12GraphicsCommandList = ptr::null_mut();
-        let hr = (*device.device).CreateCommandList(
-            0,
-            D3D12_COMMAND_LIST_TYPE_DIRECT,
-            allocator,
-            ptr::null_mut(),
-            &ID3D12GraphicsCommandList::uuidof(),
-            &mut list as *mut *mut _ as *mut *mut _
-        );
-        println!("{:?}   {:?}, {:?}", hr, allocator, list);
-
-        (*list).Close();
-
-        (allocator, list)
-    };
-
     let (fence, fence_event) = unsafe {
         let mut fence: *mut ID3D12Fence = ptr::null_mut();
         (*device.device).CreateFence(0, D3D12_FENCE_FLAG_NONE, &ID3D12Fence::uuidof(), &mut fence as *mut *mut _ as *mut *mut _);
@@ -184,7 +221,18 @@ fn main() {
 
     let mut fence_value = 1u64;
 
-    let mut fg = FrameGraph::new(device.device);
+    let back_buffers = unsafe {
+        (0..3).map(|idx| {
+            let mut buffer: *mut ID3D12Resource = ptr::null_mut();
+            (*swapchain).GetBuffer(idx, &ID3D12Resource::uuidof(), &mut buffer as *mut *mut _ as *mut *mut _);
+
+            buffer
+        }).collect::<Vec<_>>()
+    };
+
+    let mut fg = FrameGraph::new(D3D12Backend::new(device.device, queue));
+    fg.set_resolution(800, 600);
+    fg.set_profiling(true);
 
     let desc = GraphicsPipelineDescription {
         vertex_shader: ShaderBlob::from_file("Basic.v"),
@@ -237,6 +285,7 @@ fn main() {
 
     unsafe {
         let mut msg = mem::zeroed();
+        let mut frame = 0u64;
         loop {
             if PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
                 TranslateMessage(&msg);
@@ -246,83 +295,92 @@ fn main() {
             if msg.message == WM_QUIT {
                 break;
             }
-            
-
-            let color = fg.add_pass(
-                "Test",
-                |builder| {
-                    let desc = RenderTargetDesc {
-                        format: TextureFormat::RGBA8,
-                        size: TextureSize::Full,
-                        mip_levels: 1,
-                        state: InitialResourceState::Clear
-                    };
-
-                    builder.create_render_target("Color", desc)
-                },
-                Box::new(|list, color_rtv: &D3D12_CPU_DESCRIPTOR_HANDLE| {
-                    //println!("Alias: {:#x}", color_rtv.ptr);
 
-                    unsafe {
-                        (*list).ClearRenderTargetView(*color_rtv, &[0.8f32, 0.4f32, 0.3f32, 1f32], 0, ::std::ptr::null_mut());
-                    }
-                })
+            let backbuffer = fg.import_resource(
+                "BackBuffer",
+                back_buffers[(*swapchain).GetCurrentBackBufferIndex() as usize],
+                TextureFormat::RGBA8,
+                ResourceStates::PRESENT,
+                ResourceStates::PRESENT
             );
 
-            let _ = fg.add_pass(
-                "Dummy",
-                |builder| {
-                    builder.read_srv(&color)
-                },
-                Box::new(|_list, _| {
-
-                })
-            );
 
-            let color = fg.add_pass(
-                "TestAlias",
-                |builder| {
-                    let desc = RenderTargetDesc {
-                        format: TextureFormat::RGBA8,
-                        size: TextureSize::Full,
-                        mip_levels: 1,
-                        state: InitialResourceState::Clear
-                    };
-
-                    builder.create_render_target("ColorAlias", desc)
-                },
-                Box::new(|list, color_rtv: &D3D12_CPU_DESCRIPTOR_HANDLE| {
-                    //println!("Dummy Alias: {:#x}", color_rtv.ptr);
-                    unsafe {
-                        (*list).ClearRenderTargetView(*color_rtv, &[0.3f32, 0.8f32, 0.6f32, 1f32], 0, ::std::ptr::null_mut());
-                    }
-                })
-            );
+            // passes and resources in here are named "Aliasing/..."
+            let color = fg.add_subgraph("Aliasing", |fg| {
+                let color = fg.add_pass(
+                    "Test",
+                    |builder| {
+                        let desc = RenderTargetDesc {
+                            format: TextureFormat::RGBA8,
+                            size: TextureSize::Full,
+                            mip_levels: 1,
+                            array_size: 1,
+                            state: InitialResourceState::Clear,
+                            clear_color: [0.8f32, 0.4f32, 0.3f32, 1f32]
+                        };
+
+                        builder.create_render_target("Color", desc)
+                    },
+                    Box::new(|list, color_rtv: &CpuDescriptorHandle| {
+                        //println!("Alias: {:#x}", color_rtv.ptr);
+                    })
+                );
+
+                let _ = fg.add_pass(
+                    "Dummy",
+                    |builder| {
+                        // stands in for real work, keeps "Color" alive to alias with
+                        builder.set_side_effects(true);
+                        builder.read_srv(&color)
+                    },
+                    Box::new(|_list, _| {
+
+                    })
+                );
+
+                fg.add_pass(
+                    "TestAlias",
+                    |builder| {
+                        let desc = RenderTargetDesc {
+                            format: TextureFormat::RGBA8,
+                            size: TextureSize::Full,
+                            mip_levels: 1,
+                            array_size: 1,
+                            state: InitialResourceState::Clear,
+                            clear_color: [0.3f32, 0.8f32, 0.6f32, 1f32]
+                        };
+
+                        builder.create_render_target("ColorAlias", desc)
+                    },
+                    Box::new(|list, color_rtv: &CpuDescriptorHandle| {
+                        //println!("Dummy Alias: {:#x}", color_rtv.ptr);
+                    })
+                )
+            });
 
             let _ = fg.add_pass(
-                "DummyAlias",
+                "Composite",
                 |builder| {
-                    builder.read_srv(&color)
+                    builder.read_srv(&color);
+                    builder.clear_render_target(backbuffer, [0.1f32, 0.1f32, 0.1f32, 1f32])
                 },
-                Box::new(|_list, _| {
+                Box::new(|list, backbuffer_rtv: &CpuDescriptorHandle| {
 
                 })
             );
 
-            fg.compile();
-            
-            (*allocator).Reset();
-            (*list).Reset(allocator, ptr::null_mut());
-
-            fg.exec(list);
-
-            (*list).Close();
-            (*queue).ExecuteCommandLists(1, &list as *const *mut _ as *const *mut _);
+            fg.compile().expect("invalid frame graph");
+            fg.exec_queues();
 
             (*swapchain).Present(1, 0);
 
             fg.finish();
 
+            frame += 1;
+            if frame % 600 == 0 {
+                println!("{}", fg.profiler().summary());
+            }
+
             let old_value = fence_value;
             (*queue).Signal(fence, old_value);
             fence_value += 1;
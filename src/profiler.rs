@@ -0,0 +1,432 @@
+use backend::Backend;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+// frames the timestamps of a frame are in flight for before they're read
+// back, the application can't run further ahead of the GPU than this
+pub const PROFILER_LATENCY: usize = 3;
+
+// frames the summary is taken over
+const SUMMARY_FRAMES: usize = 64;
+
+// where `compile` spent its time, everything but `placement` is zero when the
+// compiled graph was reused
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CompileTimings {
+    pub cull: Duration,
+    pub schedule: Duration,
+    pub barriers: Duration,
+    pub lifetimes: Duration,
+    pub placement: Duration,
+    pub total: Duration
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassTiming {
+    pub name: String,
+    // path of the sub-graph the pass was added in, empty at the top level
+    pub subgraph: String,
+    // between the timestamps around the pass, `None` for passes recorded into
+    // copy lists
+    pub gpu: Option<Duration>,
+    // spent recording the pass, including its barriers and clears
+    pub cpu: Duration
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameTimings {
+    pub frame: u32,
+    pub compile: CompileTimings,
+    // in execution order
+    pub passes: Vec<PassTiming>
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration
+}
+
+impl TimingStats {
+    fn from_samples<I: Iterator<Item=Duration>>(samples: I) -> Option<Self> {
+        let mut count = 0;
+        let mut stats = TimingStats::default();
+        let mut sum = Duration::new(0, 0);
+
+        for sample in samples {
+            if count == 0 || sample < stats.min {
+                stats.min = sample;
+            }
+            if sample > stats.max {
+                stats.max = sample;
+            }
+
+            sum += sample;
+            count += 1;
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        stats.avg = sum / count;
+        Some(stats)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassSummary {
+    pub name: String,
+    // frames the pass (or a pass of the sub-graph) ran in
+    pub frames: usize,
+    pub gpu: Option<TimingStats>,
+    pub cpu: TimingStats
+}
+
+// min/avg/max of every pass over the last frames that were read back
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimingSummary {
+    pub frames: usize,
+    pub compile: Option<TimingStats>,
+    // in the order the passes first ran in
+    pub passes: Vec<PassSummary>,
+    // every pass of a sub-graph (nested ones included) summed up per frame,
+    // in the order the sub-graphs first ran in
+    pub subgraphs: Vec<PassSummary>
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn write_stats(f: &mut fmt::Formatter, stats: Option<TimingStats>) -> fmt::Result {
+    match stats {
+        Some(stats) => write!(f, "{:>8.3} {:>8.3} {:>8.3}", millis(stats.min), millis(stats.avg), millis(stats.max)),
+        None => write!(f, "{:>8} {:>8} {:>8}", "-", "-", "-")
+    }
+}
+
+fn write_row(f: &mut fmt::Formatter, pass: &PassSummary) -> fmt::Result {
+    write!(f, "{:<24} ", pass.name)?;
+    write_stats(f, pass.gpu)?;
+    write!(f, "   ")?;
+    write_stats(f, Some(pass.cpu))?;
+    writeln!(f)
+}
+
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} frames, times in ms", self.frames)?;
+        writeln!(f, "{:<24} {:>8} {:>8} {:>8}   {:>8} {:>8} {:>8}", "pass", "gpu min", "avg", "max", "cpu min", "avg", "max")?;
+
+        for pass in &self.passes {
+            write_row(f, pass)?;
+        }
+
+        if !self.subgraphs.is_empty() {
+            writeln!(f, "sub-graph")?;
+
+            for subgraph in &self.subgraphs {
+                write_row(f, subgraph)?;
+            }
+        }
+
+        write!(f, "{:<24} {:>26}   ", "compile", "")?;
+        write_stats(f, self.compile)?;
+        writeln!(f)
+    }
+}
+
+// a frame whose timestamps haven't been read back yet
+struct PendingFrame {
+    timings: FrameTimings,
+    // first of the two timestamps of every pass
+    queries: Vec<Option<u32>>
+}
+
+// wraps the passes in timestamp queries and collects the results a few frames
+// later, once the GPU is guaranteed to be done with them
+pub struct Profiler {
+    enabled: bool,
+    // timestamps reserved for every frame in flight, two per pass
+    capacity: u32,
+    frequency: u64,
+    pending: [Option<PendingFrame>; PROFILER_LATENCY],
+    // read back, oldest first
+    frames: VecDeque<FrameTimings>
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            enabled: false,
+            capacity: 0,
+            frequency: 0,
+            pending: [None, None, None],
+            frames: VecDeque::new()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            for pending in &mut self.pending {
+                *pending = None;
+            }
+        }
+    }
+
+    // collects the results of the frame that last used `frame`'s timestamps,
+    // and makes room for `passes` passes, returns the first timestamp of the
+    // frame
+    pub fn begin_frame<B: Backend>(&mut self, backend: &mut B, frame: u32, passes: usize) -> Option<u32> {
+        if !self.enabled {
+            return None;
+        }
+
+        let slot = frame as usize % PROFILER_LATENCY;
+        if let Some(pending) = self.pending[slot].take() {
+            self.read_back(backend, slot, pending);
+        }
+
+        let needed = passes as u32 * 2;
+        if needed > self.capacity {
+            // recreating the queries drops the results still in flight
+            self.capacity = needed.next_power_of_two();
+            self.frequency = backend.timestamp_frequency();
+            backend.create_timestamp_queries(self.capacity * PROFILER_LATENCY as u32);
+
+            for pending in &mut self.pending {
+                *pending = None;
+            }
+        }
+
+        Some(slot as u32 * self.capacity)
+    }
+
+    pub fn end_frame(&mut self, timings: FrameTimings, queries: Vec<Option<u32>>) {
+        if !self.enabled {
+            return;
+        }
+
+        let slot = timings.frame as usize % PROFILER_LATENCY;
+        self.pending[slot] = Some(PendingFrame {
+            timings,
+            queries
+        });
+    }
+
+    fn read_back<B: Backend>(&mut self, backend: &B, slot: usize, pending: PendingFrame) {
+        let PendingFrame { mut timings, queries } = pending;
+
+        let mut timestamps = vec![0u64; self.capacity as usize];
+        backend.read_timestamps(slot as u32 * self.capacity, &mut timestamps);
+
+        let base = slot as u32 * self.capacity;
+        for (pass, query) in timings.passes.iter_mut().zip(queries) {
+            pass.gpu = query.map(|query| {
+                let idx = (query - base) as usize;
+                let ticks = timestamps[idx + 1].saturating_sub(timestamps[idx]);
+
+                Duration::from_nanos((ticks as u128 * 1_000_000_000 / self.frequency.max(1) as u128) as u64)
+            });
+        }
+
+        if self.frames.len() == SUMMARY_FRAMES {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(timings);
+    }
+
+    // the most recent frame that was read back
+    pub fn latest(&self) -> Option<&FrameTimings> {
+        self.frames.back()
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item=&FrameTimings> {
+        self.frames.iter()
+    }
+
+    pub fn summary(&self) -> TimingSummary {
+        let mut names: Vec<&str> = Vec::new();
+        let mut subgraphs: Vec<&str> = Vec::new();
+        for frame in &self.frames {
+            for pass in &frame.passes {
+                if !names.contains(&&pass.name[..]) {
+                    names.push(&pass.name);
+                }
+
+                // "Bloom/Down2" counts towards "Bloom" as well
+                let path = &pass.subgraph[..];
+                let ends = path.match_indices('/').map(|(idx, _)| idx).chain(if path.is_empty() { None } else { Some(path.len()) });
+                for end in ends {
+                    if !subgraphs.contains(&&path[..end]) {
+                        subgraphs.push(&path[..end]);
+                    }
+                }
+            }
+        }
+
+        let passes = names.into_iter().map(|name| {
+            let samples = || self.frames.iter().flat_map(|frame| frame.passes.iter()).filter(move |pass| pass.name == name);
+
+            PassSummary {
+                name: name.to_string(),
+                frames: samples().count(),
+                gpu: TimingStats::from_samples(samples().filter_map(|pass| pass.gpu)),
+                cpu: TimingStats::from_samples(samples().map(|pass| pass.cpu)).unwrap_or_default()
+            }
+        }).collect();
+
+        let subgraphs = subgraphs.into_iter().map(|path| {
+            let contains = |pass: &PassTiming| pass.subgraph == path || (pass.subgraph.starts_with(path) && pass.subgraph[path.len()..].starts_with('/'));
+
+            // (gpu, cpu) of every frame the sub-graph ran in, the gpu time is
+            // only known if every one of its passes was timed
+            let samples = self.frames.iter().filter_map(|frame| {
+                let passes = frame.passes.iter().filter(|pass| contains(pass)).collect::<Vec<_>>();
+                if passes.is_empty() {
+                    return None;
+                }
+
+                let gpu = passes.iter().map(|pass| pass.gpu).sum::<Option<Duration>>();
+                let cpu = passes.iter().map(|pass| pass.cpu).sum::<Duration>();
+                Some((gpu, cpu))
+            }).collect::<Vec<_>>();
+
+            PassSummary {
+                name: path.to_string(),
+                frames: samples.len(),
+                gpu: TimingStats::from_samples(samples.iter().filter_map(|&(gpu, _)| gpu)),
+                cpu: TimingStats::from_samples(samples.iter().map(|&(_, cpu)| cpu)).unwrap_or_default()
+            }
+        }).collect();
+
+        TimingSummary {
+            frames: self.frames.len(),
+            compile: TimingStats::from_samples(self.frames.iter().map(|frame| frame.compile.total)),
+            passes,
+            subgraphs
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use backend::QueueType;
+    use recording::RecordingBackend;
+
+    // (name, sub-graph, commands recorded, cpu time in microseconds) of every
+    // pass, the recording backend ticks once per command
+    fn record(profiler: &mut Profiler, backend: &mut RecordingBackend, frame: u32, passes: &[(&str, &str, u32, u64)]) {
+        let base = profiler.begin_frame(backend, frame, passes.len()).unwrap();
+        let list = backend.begin_commands(QueueType::Graphics);
+
+        let mut timings = Vec::new();
+        let mut queries = Vec::new();
+        for (idx, &(name, subgraph, commands, cpu)) in passes.iter().enumerate() {
+            let query = base + idx as u32 * 2;
+
+            backend.write_timestamp(list, query);
+            for _ in 0..commands {
+                backend.execute_pass(list, name);
+            }
+            backend.write_timestamp(list, query + 1);
+
+            timings.push(PassTiming {
+                name: name.to_string(),
+                subgraph: subgraph.to_string(),
+                gpu: None,
+                cpu: Duration::from_micros(cpu)
+            });
+            queries.push(Some(query));
+        }
+        backend.resolve_timestamps(list, base, passes.len() as u32 * 2);
+
+        profiler.end_frame(FrameTimings {
+            frame,
+            compile: CompileTimings::default(),
+            passes: timings
+        }, queries);
+    }
+
+    fn profiler() -> (Profiler, RecordingBackend) {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+
+        (profiler, RecordingBackend::new())
+    }
+
+    #[test]
+    fn frames_are_read_back_after_the_latency() {
+        let (mut profiler, mut backend) = profiler();
+
+        for frame in 0..PROFILER_LATENCY as u32 {
+            record(&mut profiler, &mut backend, frame, &[("Composite", "", 1, 100)]);
+            assert!(profiler.latest().is_none());
+        }
+
+        // the first frame's timestamps are reused, so it has to be done
+        profiler.begin_frame(&mut backend, PROFILER_LATENCY as u32, 1);
+        assert_eq!(profiler.latest().map(|frame| frame.frame), Some(0));
+    }
+
+    #[test]
+    fn pass_durations_come_from_their_timestamps() {
+        let (mut profiler, mut backend) = profiler();
+
+        record(&mut profiler, &mut backend, 0, &[("GBuffer", "", 2, 100), ("Lighting", "", 5, 100)]);
+        profiler.begin_frame(&mut backend, PROFILER_LATENCY as u32, 2);
+
+        // the closing timestamp counts the opening one as well
+        let gpu = profiler.latest().unwrap().passes.iter().map(|pass| pass.gpu).collect::<Vec<_>>();
+        assert_eq!(gpu, vec![Some(Duration::from_micros(3)), Some(Duration::from_micros(6))]);
+    }
+
+    #[test]
+    fn summary_covers_the_last_frames_and_adds_up_sub_graphs() {
+        let (mut profiler, mut backend) = profiler();
+
+        let frames = (SUMMARY_FRAMES + PROFILER_LATENCY + 2) as u32;
+        for frame in 0..frames {
+            let cpu = frame as u64;
+            record(&mut profiler, &mut backend, frame, &[
+                ("Composite", "", 1, cpu),
+                ("Bloom/Down", "Bloom", 2, cpu),
+                ("Bloom/Mip/Down", "Bloom/Mip", 4, cpu)
+            ]);
+        }
+
+        // frames 2 to 65 were read back last
+        let summary = profiler.summary();
+        assert_eq!(summary.frames, SUMMARY_FRAMES);
+
+        let composite = &summary.passes[0];
+        assert_eq!(composite.name, "Composite");
+        assert_eq!(composite.frames, SUMMARY_FRAMES);
+        assert_eq!(composite.cpu, TimingStats {
+            min: Duration::from_micros(2),
+            avg: Duration::from_nanos(33_500),
+            max: Duration::from_micros(65)
+        });
+
+        let names = summary.subgraphs.iter().map(|subgraph| &subgraph.name[..]).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Bloom", "Bloom/Mip"]);
+
+        let bloom = &summary.subgraphs[0];
+        assert_eq!(bloom.gpu.map(|gpu| gpu.avg), Some(Duration::from_micros(3 + 5)));
+        assert_eq!(bloom.cpu.avg, Duration::from_micros(67));
+        assert_eq!(summary.subgraphs[1].gpu.map(|gpu| gpu.avg), Some(Duration::from_micros(5)));
+    }
+}
@@ -0,0 +1,365 @@
+use backend::{
+    Backend,
+    ClearValue,
+    CpuDescriptorHandle,
+    GpuDescriptorHandle,
+    HeapFlags,
+    QueueType,
+    ResourceBarrier,
+    ResourceDesc,
+    RenderPassAttachment,
+    ResourceDimension,
+    ResourceStates,
+    ResourceViewDesc
+};
+
+use std::sync::{
+    Mutex,
+    MutexGuard
+};
+
+// records everything the frame graph asks of a device, so graph compilation
+// can be inspected without a GPU
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedCommand {
+    BeginCommands {
+        queue: QueueType,
+        list: u32
+    },
+    SubmitCommands {
+        queue: QueueType,
+        list: u32,
+        waits: Vec<(QueueType, u64)>,
+        signal: Option<u64>
+    },
+    CreateHeap {
+        heap: u32,
+        size: u64,
+        flags: HeapFlags
+    },
+    ReleaseHeap {
+        heap: u32
+    },
+    CreatePlacedResource {
+        resource: u32,
+        heap: u32,
+        offset: u64,
+        desc: ResourceDesc,
+        state: ResourceStates,
+        clear_value: Option<ClearValue>,
+        name: String
+    },
+    ReleaseResource {
+        resource: u32
+    },
+    CreateView {
+        resource: u32,
+        view_id: u32,
+        desc: ResourceViewDesc
+    },
+    Barrier {
+        list: u32,
+        barrier: ResourceBarrier<u32>
+    },
+    ClearRenderTarget {
+        list: u32,
+        view_id: u32,
+        color: [f32; 4]
+    },
+    ClearDepthStencil {
+        list: u32,
+        view_id: u32,
+        depth: f32,
+        stencil: u8
+    },
+    DiscardResource {
+        list: u32,
+        resource: u32
+    },
+    BeginRenderPass {
+        list: u32,
+        render_targets: Vec<RenderPassAttachment>,
+        depth: Option<RenderPassAttachment>
+    },
+    EndRenderPass {
+        list: u32
+    },
+    WriteTimestamp {
+        list: u32,
+        index: u32
+    },
+    ResolveTimestamps {
+        list: u32,
+        first: u32,
+        count: u32
+    },
+    BeginEvent {
+        list: u32,
+        name: String
+    },
+    EndEvent {
+        list: u32
+    },
+    ExecutePass {
+        list: u32,
+        name: String
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordingBackend {
+    // lists can be recorded from several threads
+    log: Mutex<Vec<RecordedCommand>>,
+    // timestamps count the commands recorded before them, so a pass's gpu
+    // time is the number of commands it recorded
+    timestamps: Mutex<Vec<u64>>,
+    readback: Mutex<Vec<u64>>,
+    heap_counter: u32,
+    resource_counter: u32,
+    list_counter: u32
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        RecordingBackend {
+            log: Mutex::new(Vec::new()),
+            timestamps: Mutex::new(Vec::new()),
+            readback: Mutex::new(Vec::new()),
+            heap_counter: 0,
+            resource_counter: 0,
+            list_counter: 0
+        }
+    }
+
+    pub fn log(&self) -> MutexGuard<'_, Vec<RecordedCommand>> {
+        self.log.lock().unwrap()
+    }
+
+    pub fn clear(&mut self) {
+        self.log().clear();
+    }
+}
+
+// default placement alignment for buffers and textures
+const RESOURCE_ALIGNMENT: u64 = 64 * 1024;
+
+impl Backend for RecordingBackend {
+    type Heap = u32;
+    type Resource = u32;
+    type CommandList = u32;
+
+    fn begin_commands(&mut self, queue: QueueType) -> u32 {
+        let list = self.list_counter;
+        self.list_counter += 1;
+
+        self.log().push(RecordedCommand::BeginCommands {
+            queue,
+            list
+        });
+
+        list
+    }
+
+    fn submit_commands(&mut self, queue: QueueType, list: u32, waits: &[(QueueType, u64)], signal: Option<u64>) {
+        self.log().push(RecordedCommand::SubmitCommands {
+            queue,
+            list,
+            waits: waits.to_vec(),
+            signal
+        });
+    }
+
+    fn resource_allocation_info(&self, desc: &ResourceDesc) -> (u64, u64) {
+        if desc.dimension == ResourceDimension::Buffer {
+            let size = desc.width.div_ceil(RESOURCE_ALIGNMENT) * RESOURCE_ALIGNMENT;
+
+            return (size, RESOURCE_ALIGNMENT);
+        }
+
+        let mut size = 0;
+        let mut width = desc.width;
+        let mut height = desc.height as u64;
+
+        for _ in 0..desc.mip_levels.max(1) {
+            size += width * height * desc.depth_or_array_size as u64 * desc.format.size();
+
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+        }
+
+        let size = size.div_ceil(RESOURCE_ALIGNMENT) * RESOURCE_ALIGNMENT;
+
+        (size, RESOURCE_ALIGNMENT)
+    }
+
+    fn create_heap(&mut self, size: u64, flags: HeapFlags) -> u32 {
+        let heap = self.heap_counter;
+        self.heap_counter += 1;
+
+        self.log().push(RecordedCommand::CreateHeap {
+            heap,
+            size,
+            flags
+        });
+
+        heap
+    }
+
+    fn release_heap(&mut self, heap: u32) {
+        self.log().push(RecordedCommand::ReleaseHeap {
+            heap
+        });
+    }
+
+    fn create_placed_resource(&mut self, heap: u32, offset: u64, desc: &ResourceDesc, state: ResourceStates, clear_value: Option<ClearValue>, name: &str) -> u32 {
+        let resource = self.resource_counter;
+        self.resource_counter += 1;
+
+        self.log().push(RecordedCommand::CreatePlacedResource {
+            resource,
+            heap,
+            offset,
+            desc: *desc,
+            state,
+            clear_value,
+            name: name.to_owned()
+        });
+
+        resource
+    }
+
+    fn release_resource(&mut self, resource: u32) {
+        self.log().push(RecordedCommand::ReleaseResource {
+            resource
+        });
+    }
+
+    fn create_view(&mut self, resource: u32, view_id: u32, desc: &ResourceViewDesc) {
+        self.log().push(RecordedCommand::CreateView {
+            resource,
+            view_id,
+            desc: *desc
+        });
+    }
+
+    fn cpu_handle(&self, view_id: u32) -> CpuDescriptorHandle {
+        CpuDescriptorHandle {
+            ptr: view_id as usize
+        }
+    }
+
+    fn gpu_handle(&self, view_id: u32) -> GpuDescriptorHandle {
+        GpuDescriptorHandle {
+            ptr: view_id as u64
+        }
+    }
+
+    fn dsv_handle(&self, view_id: u32) -> CpuDescriptorHandle {
+        CpuDescriptorHandle {
+            ptr: view_id as usize
+        }
+    }
+
+    fn resource_barrier(&self, list: u32, barriers: &[ResourceBarrier<u32>]) {
+        for &barrier in barriers {
+            self.log().push(RecordedCommand::Barrier {
+                list,
+                barrier
+            });
+        }
+    }
+
+    fn clear_render_target(&self, list: u32, view_id: u32, color: [f32; 4]) {
+        self.log().push(RecordedCommand::ClearRenderTarget {
+            list,
+            view_id,
+            color
+        });
+    }
+
+    fn clear_depth_stencil(&self, list: u32, view_id: u32, depth: f32, stencil: u8) {
+        self.log().push(RecordedCommand::ClearDepthStencil {
+            list,
+            view_id,
+            depth,
+            stencil
+        });
+    }
+
+    fn discard_resource(&self, list: u32, resource: u32) {
+        self.log().push(RecordedCommand::DiscardResource {
+            list,
+            resource
+        });
+    }
+
+    fn begin_render_pass(&self, list: u32, render_targets: &[RenderPassAttachment], depth: Option<RenderPassAttachment>) {
+        self.log().push(RecordedCommand::BeginRenderPass {
+            list,
+            render_targets: render_targets.to_vec(),
+            depth
+        });
+    }
+
+    fn end_render_pass(&self, list: u32) {
+        self.log().push(RecordedCommand::EndRenderPass {
+            list
+        });
+    }
+
+    fn create_timestamp_queries(&mut self, count: u32) {
+        *self.timestamps.lock().unwrap() = vec![0; count as usize];
+        *self.readback.lock().unwrap() = vec![0; count as usize];
+    }
+
+    fn write_timestamp(&self, list: u32, index: u32) {
+        let mut log = self.log();
+
+        self.timestamps.lock().unwrap()[index as usize] = log.len() as u64;
+        log.push(RecordedCommand::WriteTimestamp {
+            list,
+            index
+        });
+    }
+
+    fn resolve_timestamps(&self, list: u32, first: u32, count: u32) {
+        let range = first as usize..(first + count) as usize;
+        self.readback.lock().unwrap()[range.clone()].copy_from_slice(&self.timestamps.lock().unwrap()[range]);
+
+        self.log().push(RecordedCommand::ResolveTimestamps {
+            list,
+            first,
+            count
+        });
+    }
+
+    fn read_timestamps(&self, first: u32, timestamps: &mut [u64]) {
+        let first = first as usize;
+        timestamps.copy_from_slice(&self.readback.lock().unwrap()[first..first + timestamps.len()]);
+    }
+
+    // one tick per microsecond
+    fn timestamp_frequency(&self) -> u64 {
+        1_000_000
+    }
+
+    fn begin_event(&self, list: u32, name: &str) {
+        self.log().push(RecordedCommand::BeginEvent {
+            list,
+            name: name.to_owned()
+        });
+    }
+
+    fn end_event(&self, list: u32) {
+        self.log().push(RecordedCommand::EndEvent {
+            list
+        });
+    }
+
+    fn execute_pass(&self, list: u32, name: &str) {
+        self.log().push(RecordedCommand::ExecutePass {
+            list,
+            name: name.to_string()
+        });
+    }
+}
@@ -0,0 +1,525 @@
+use backend::{
+    QueueType,
+    ResourceStates
+};
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct QueuePass {
+    pub queue: QueueType,
+    pub resources: Vec<u32>,
+    // the pass has transitions its own queue can't record, they go at the end
+    // of the graphics batch before it instead
+    pub graphics_transitions: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueBatch {
+    pub queue: QueueType,
+    // recorded into one command list, in schedule order
+    pub passes: Vec<usize>,
+    // (queue, fence value) pairs the batch waits on before it executes
+    pub waits: Vec<(QueueType, u64)>,
+    // value the queue's fence reaches once the batch has executed, values
+    // grow by one per batch on the same queue
+    pub fence_value: u64,
+    // whether anything waits on the batch, so the fence has to be signalled
+    pub signal: bool
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueuePlan {
+    pub batches: Vec<QueueBatch>,
+    // batch each pass is recorded into
+    pub pass_batch: Vec<usize>,
+    // graphics batch that records the transitions a pass's queue can't
+    pub transition_batch: Vec<Option<usize>>,
+    // per batch, the highest fence value of every queue that is known to be
+    // ordered before the batch starts
+    reach: Vec<[u64; 3]>
+}
+
+impl QueuePlan {
+    // pass `a` is guaranteed to finish before pass `b` starts
+    pub fn ordered(&self, a: usize, b: usize) -> bool {
+        let (batch_a, batch_b) = (self.pass_batch[a], self.pass_batch[b]);
+
+        if batch_a == batch_b {
+            return a < b;
+        }
+
+        let batch = &self.batches[batch_a];
+        batch.fence_value <= self.reach[batch_b][batch.queue.index()]
+    }
+
+    // first and last pass that may execute at the same time as `pass`
+    pub fn concurrent_range(&self, pass: usize) -> (usize, usize) {
+        let mut range = (pass, pass);
+
+        for other in 0..self.pass_batch.len() {
+            if other != pass && !self.ordered(pass, other) && !self.ordered(other, pass) {
+                range.0 = range.0.min(other);
+                range.1 = range.1.max(other);
+            }
+        }
+
+        range
+    }
+}
+
+// splits a schedule into per-queue batches
+//
+// a batch can only wait on other queues before it starts, so a pass that
+// touches a resource last used on another queue closes the batch it depends
+// on (which then signals its fence) and starts a new batch of its own that
+// waits on it
+pub fn plan_queues(passes: &[QueuePass]) -> QueuePlan {
+    let mut plan = QueuePlan::default();
+
+    let mut open: [Option<usize>; 3] = [None; 3];
+    let mut latest: [Option<usize>; 3] = [None; 3];
+    let mut values = [0u64; 3];
+    let mut last_batch: HashMap<u32, usize> = HashMap::new();
+
+    for (idx, pass) in passes.iter().enumerate() {
+        let queue = pass.queue.index();
+
+        let mut deps = Vec::new();
+        for resource in &pass.resources {
+            if let Some(&batch) = last_batch.get(resource) {
+                if plan.batches[batch].queue != pass.queue && !deps.contains(&batch) {
+                    deps.push(batch);
+                }
+            }
+        }
+
+        let mut transition_batch = None;
+        if pass.graphics_transitions && pass.queue != QueueType::Graphics {
+            let graphics = QueueType::Graphics.index();
+
+            let batch = match latest[graphics] {
+                Some(batch) => batch,
+                None => {
+                    // nothing to piggyback on, the transitions get a batch
+                    // of their own
+                    values[graphics] += 1;
+                    plan.batches.push(QueueBatch {
+                        queue: QueueType::Graphics,
+                        passes: Vec::new(),
+                        waits: Vec::new(),
+                        fence_value: values[graphics],
+                        signal: false
+                    });
+                    plan.reach.push([0; 3]);
+
+                    latest[graphics] = Some(plan.batches.len() - 1);
+                    plan.batches.len() - 1
+                }
+            };
+
+            if !deps.contains(&batch) {
+                deps.push(batch);
+            }
+
+            transition_batch = Some(batch);
+        }
+
+        if !deps.is_empty() {
+            for &batch in &deps {
+                let dep_queue = plan.batches[batch].queue.index();
+
+                plan.batches[batch].signal = true;
+                if open[dep_queue] == Some(batch) {
+                    open[dep_queue] = None;
+                }
+            }
+
+            open[queue] = None;
+        }
+
+        let batch = match open[queue] {
+            Some(batch) => batch,
+            None => {
+                let mut reach = match latest[queue] {
+                    Some(prev) => {
+                        let mut reach = plan.reach[prev];
+                        reach[queue] = plan.batches[prev].fence_value;
+                        reach
+                    },
+                    None => [0; 3]
+                };
+
+                let mut waits: Vec<(QueueType, u64)> = Vec::new();
+                for &dep in &deps {
+                    let dep_batch = &plan.batches[dep];
+                    let dep_queue = dep_batch.queue.index();
+
+                    for (reach, &dep_reach) in reach.iter_mut().zip(&plan.reach[dep]) {
+                        *reach = (*reach).max(dep_reach);
+                    }
+                    reach[dep_queue] = reach[dep_queue].max(dep_batch.fence_value);
+
+                    match waits.iter().position(|&(q, _)| q == dep_batch.queue) {
+                        Some(wait) => waits[wait].1 = waits[wait].1.max(dep_batch.fence_value),
+                        None => waits.push((dep_batch.queue, dep_batch.fence_value))
+                    }
+                }
+
+                values[queue] += 1;
+                plan.batches.push(QueueBatch {
+                    queue: pass.queue,
+                    passes: Vec::new(),
+                    waits,
+                    fence_value: values[queue],
+                    signal: false
+                });
+                plan.reach.push(reach);
+
+                let batch = plan.batches.len() - 1;
+                open[queue] = Some(batch);
+                latest[queue] = Some(batch);
+
+                batch
+            }
+        };
+
+        plan.batches[batch].passes.push(idx);
+        plan.pass_batch.push(batch);
+        plan.transition_batch.push(transition_batch);
+
+        for &resource in &pass.resources {
+            last_batch.insert(resource, batch);
+        }
+    }
+
+    plan
+}
+
+// order `compile` executes the passes in, every strategy keeps the order of
+// passes that depend on each other
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum ScheduleStrategy {
+    // `add_pass` order
+    #[default]
+    Declaration,
+    // start resources as late and finish them as early as possible, so more
+    // of them can alias
+    MinimizeMemory,
+    // keep passes using resources in the same state together
+    MinimizeBarriers
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderPass {
+    // (resource, writes, state)
+    pub accesses: Vec<(u32, bool, ResourceStates)>,
+    // passes with side effects keep their order among each other
+    pub side_effects: bool
+}
+
+// passes that have to run before each pass: writes are ordered against every
+// other access of the resource, reads only against writes
+fn dependencies(passes: &[OrderPass], resource_count: usize) -> Vec<Vec<usize>> {
+    let mut deps = vec![Vec::new(); passes.len()];
+    let mut last_write: Vec<Option<usize>> = vec![None; resource_count];
+    let mut reads: Vec<Vec<usize>> = vec![Vec::new(); resource_count];
+    let mut last_side_effect = None;
+
+    for (idx, pass) in passes.iter().enumerate() {
+        let add = |deps: &mut Vec<usize>, dep: usize| {
+            if dep != idx && !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        };
+
+        for &(resource, _, _) in &pass.accesses {
+            let res = resource as usize;
+            let writes = pass.accesses.iter().any(|&(other, write, _)| other == resource && write);
+
+            if let Some(writer) = last_write[res] {
+                add(&mut deps[idx], writer);
+            }
+
+            if writes {
+                for &reader in &reads[res] {
+                    add(&mut deps[idx], reader);
+                }
+            }
+        }
+
+        for &(resource, _, _) in &pass.accesses {
+            let res = resource as usize;
+            let writes = pass.accesses.iter().any(|&(other, write, _)| other == resource && write);
+
+            if writes {
+                last_write[res] = Some(idx);
+                reads[res].clear();
+            } else if !reads[res].contains(&idx) {
+                reads[res].push(idx);
+            }
+        }
+
+        if pass.side_effects {
+            if let Some(prev) = last_side_effect {
+                add(&mut deps[idx], prev);
+            }
+            last_side_effect = Some(idx);
+        }
+    }
+
+    deps
+}
+
+// greedy topological sort, picks the ready pass that is best for `strategy`
+// and falls back to declaration order on ties
+//
+// `sizes` are the bytes every resource takes up in a heap, zero for the ones
+// that aren't placed
+pub fn order_passes(passes: &[OrderPass], sizes: &[u64], strategy: ScheduleStrategy) -> Vec<usize> {
+    if strategy == ScheduleStrategy::Declaration {
+        return (0..passes.len()).collect();
+    }
+
+    let deps = dependencies(passes, sizes.len());
+
+    let mut scheduled = vec![false; passes.len()];
+    let mut remaining_uses = vec![0usize; sizes.len()];
+    let mut states: Vec<Option<ResourceStates>> = vec![None; sizes.len()];
+    let mut order = Vec::with_capacity(passes.len());
+
+    for pass in passes {
+        let mut used = pass.accesses.iter().map(|access| access.0).collect::<Vec<_>>();
+        used.sort();
+        used.dedup();
+
+        for res in used {
+            remaining_uses[res as usize] += 1;
+        }
+    }
+
+    let total_uses = remaining_uses.clone();
+
+    while order.len() < passes.len() {
+        let ready = (0..passes.len())
+            .filter(|&idx| !scheduled[idx] && deps[idx].iter().all(|&dep| scheduled[dep]));
+
+        let cost = |idx: usize| -> i64 {
+            let mut used = passes[idx].accesses.clone();
+            used.sort_by_key(|access| access.0);
+            used.dedup_by_key(|access| access.0);
+
+            match strategy {
+                // bytes this pass brings to life minus the ones it's the last user of
+                ScheduleStrategy::MinimizeMemory => used.iter().map(|&(res, _, _)| {
+                    let res = res as usize;
+                    let mut cost = 0;
+
+                    if remaining_uses[res] == total_uses[res] {
+                        cost += sizes[res] as i64;
+                    }
+                    if remaining_uses[res] == 1 {
+                        cost -= sizes[res] as i64;
+                    }
+
+                    cost
+                }).sum(),
+                ScheduleStrategy::MinimizeBarriers => passes[idx].accesses.iter().filter(|&&(res, _, state)| {
+                    match states[res as usize] {
+                        Some(current) => current != state,
+                        None => false
+                    }
+                }).count() as i64,
+                ScheduleStrategy::Declaration => 0
+            }
+        };
+
+        let next = ready.min_by_key(|&idx| (cost(idx), idx)).expect("pass dependencies form a cycle");
+
+        scheduled[next] = true;
+        order.push(next);
+
+        let mut used = passes[next].accesses.iter().map(|access| access.0).collect::<Vec<_>>();
+        used.sort();
+        used.dedup();
+
+        for res in used {
+            remaining_uses[res as usize] -= 1;
+        }
+
+        for &(res, _, state) in &passes[next].accesses {
+            states[res as usize] = Some(state);
+        }
+    }
+
+    order
+}
+
+// highest sum of the sizes of all resources alive at the same time when the
+// passes run in `order`
+pub fn peak_memory(passes: &[OrderPass], order: &[usize], sizes: &[u64]) -> u64 {
+    let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; sizes.len()];
+
+    for (position, &idx) in order.iter().enumerate() {
+        for &(res, _, _) in &passes[idx].accesses {
+            let lifetime = &mut lifetimes[res as usize];
+            *lifetime = match *lifetime {
+                Some((start, _)) => Some((start, position)),
+                None => Some((position, position))
+            };
+        }
+    }
+
+    (0..order.len()).map(|position| {
+        lifetimes.iter().zip(sizes).filter_map(|(lifetime, &size)| match *lifetime {
+            Some((start, end)) if start <= position && position <= end => Some(size),
+            _ => None
+        }).sum()
+    }).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READ: ResourceStates = ResourceStates::PIXEL_SHADER_RESOURCE;
+    const WRITE: ResourceStates = ResourceStates::RENDER_TARGET;
+
+    fn pass(reads: &[u32], writes: &[u32]) -> OrderPass {
+        OrderPass {
+            accesses: reads.iter().map(|&res| (res, false, READ)).chain(writes.iter().map(|&res| (res, true, WRITE))).collect(),
+            side_effects: false
+        }
+    }
+
+    // every pass runs after the ones it depends on in declaration order
+    fn assert_dependencies(passes: &[OrderPass], order: &[usize]) {
+        let position = |idx: usize| order.iter().position(|&other| other == idx).unwrap();
+
+        for (idx, deps) in dependencies(passes, 8).iter().enumerate() {
+            for &dep in deps {
+                assert!(position(dep) < position(idx), "pass {} scheduled before its dependency {} in {:?}", idx, dep, order);
+            }
+        }
+    }
+
+    // two independent chains producing a large intermediate each, declared
+    // so both intermediates are alive at once
+    fn interleaved() -> (Vec<OrderPass>, Vec<u64>) {
+        let passes = vec![
+            pass(&[], &[0]),
+            pass(&[], &[1]),
+            pass(&[0], &[2]),
+            pass(&[1], &[3])
+        ];
+
+        (passes, vec![100, 60, 0, 0])
+    }
+
+    #[test]
+    fn declaration_keeps_order() {
+        let (passes, sizes) = interleaved();
+
+        assert_eq!(order_passes(&passes, &sizes, ScheduleStrategy::Declaration), vec![0, 1, 2, 3]);
+        assert_eq!(ScheduleStrategy::default(), ScheduleStrategy::Declaration);
+    }
+
+    #[test]
+    fn minimize_memory_lowers_peak() {
+        let (passes, sizes) = interleaved();
+
+        let declared = order_passes(&passes, &sizes, ScheduleStrategy::Declaration);
+        let order = order_passes(&passes, &sizes, ScheduleStrategy::MinimizeMemory);
+
+        assert_eq!(peak_memory(&passes, &declared, &sizes), 160);
+        assert_eq!(peak_memory(&passes, &order, &sizes), 100);
+        assert_dependencies(&passes, &order);
+    }
+
+    #[test]
+    fn strategies_respect_dependencies() {
+        // write after read of 0, a read-modify chain on 1 and a pass reading
+        // both in the end
+        let passes = vec![
+            pass(&[], &[0]),
+            pass(&[0], &[1]),
+            pass(&[], &[0]),
+            pass(&[1], &[2]),
+            pass(&[0, 2], &[3]),
+            pass(&[], &[4])
+        ];
+        let sizes = vec![10, 20, 30, 0, 50];
+
+        for &strategy in &[ScheduleStrategy::MinimizeMemory, ScheduleStrategy::MinimizeBarriers] {
+            let order = order_passes(&passes, &sizes, strategy);
+
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..passes.len()).collect::<Vec<_>>());
+            assert_dependencies(&passes, &order);
+        }
+    }
+
+    #[test]
+    fn side_effects_keep_their_order() {
+        let mut passes = vec![pass(&[], &[0]), pass(&[], &[1]), pass(&[], &[2])];
+        passes[0].side_effects = true;
+        passes[2].side_effects = true;
+
+        // the last pass frees the most, but can't overtake the first
+        let order = order_passes(&passes, &[50, 10, 0], ScheduleStrategy::MinimizeMemory);
+
+        assert!(order.iter().position(|&idx| idx == 0) < order.iter().position(|&idx| idx == 2));
+    }
+
+    fn queue_pass(queue: QueueType, resources: &[u32]) -> QueuePass {
+        QueuePass {
+            queue,
+            resources: resources.to_vec(),
+            graphics_transitions: false
+        }
+    }
+
+    #[test]
+    fn cross_queue_uses_wait_on_fences() {
+        // shadows on graphics, light culling on compute reading the depth,
+        // then shading on graphics reading the light lists
+        let passes = vec![
+            queue_pass(QueueType::Graphics, &[0]),
+            queue_pass(QueueType::Compute, &[0, 1]),
+            queue_pass(QueueType::Graphics, &[2]),
+            queue_pass(QueueType::Graphics, &[1, 2])
+        ];
+
+        let plan = plan_queues(&passes);
+
+        assert_eq!(plan.batches, vec![
+            QueueBatch { queue: QueueType::Graphics, passes: vec![0], waits: vec![], fence_value: 1, signal: true },
+            QueueBatch { queue: QueueType::Compute, passes: vec![1], waits: vec![(QueueType::Graphics, 1)], fence_value: 1, signal: true },
+            QueueBatch { queue: QueueType::Graphics, passes: vec![2], waits: vec![], fence_value: 2, signal: false },
+            QueueBatch { queue: QueueType::Graphics, passes: vec![3], waits: vec![(QueueType::Compute, 1)], fence_value: 3, signal: false }
+        ]);
+        assert_eq!(plan.pass_batch, vec![0, 1, 2, 3]);
+
+        // the compute pass can overlap the graphics pass after it, and
+        // nothing else
+        assert!(plan.ordered(0, 1) && plan.ordered(1, 3));
+        assert!(!plan.ordered(1, 2) && !plan.ordered(2, 1));
+        assert_eq!(plan.concurrent_range(1), (1, 2));
+        assert_eq!(plan.concurrent_range(0), (0, 0));
+    }
+
+    #[test]
+    fn unsupported_transitions_get_a_graphics_batch() {
+        let mut compute = queue_pass(QueueType::Compute, &[0]);
+        compute.graphics_transitions = true;
+
+        let plan = plan_queues(&[compute, queue_pass(QueueType::Graphics, &[0])]);
+
+        // nothing ran on graphics before, so the transitions get an empty
+        // batch the compute pass waits on
+        assert_eq!(plan.batches[0], QueueBatch { queue: QueueType::Graphics, passes: vec![], waits: vec![], fence_value: 1, signal: true });
+        assert_eq!(plan.batches[1].waits, vec![(QueueType::Graphics, 1)]);
+        assert_eq!(plan.transition_batch, vec![Some(0), None]);
+    }
+}